@@ -172,7 +172,10 @@ pub mod optim;
 
 use nodes::*;
 
-pub use nodes::{Bor, HogwildParameter, IndexInputNode, InputNode, Node, ParameterNode};
+pub use nodes::{
+    is_training, set_training, BagInputNode, Bags, Bor, CsrMatrix, CustomOp, Distance,
+    HogwildParameter, IndexInputNode, InputNode, Node, ParameterNode, Pooling, SparseInputNode,
+};
 pub use numerics::simd_dot;
 
 fn clamp(x: f32, min: f32, max: f32) -> f32 {
@@ -185,6 +188,10 @@ fn clamp(x: f32, min: f32, max: f32) -> f32 {
     }
 }
 
+fn num_segments(segment_ids: &[usize]) -> usize {
+    segment_ids.iter().max().map_or(0, |&x| x + 1)
+}
+
 /// Trait describing nodes that can accept new values once
 /// the graph has been defined.
 pub trait DataInput<T> {
@@ -308,17 +315,30 @@ where
         }
     }
 
-    /// Clip the value. Useful for clipping losses.
-    pub fn clip(&self, min: f32, max: f32) {
-        let bor_value = self.node.value();
-        let value: &Arr = bor_value.deref();
-        let value = unsafe { &mut *(value as *const Arr as *mut Arr) };
+    /// Clamp the value of this variable to lie between `min` and `max`.
+    /// Gradients only flow through elements that were within the bounds.
+    /// Useful for clipping losses.
+    pub fn clamp(&self, min: f32, max: f32) -> Variable<ClampNode<T>> {
+        Variable::new(
+            Rc::new(ClampNode::new(Rc::clone(&self.node), Clamp::new(min, max))),
+            self.parameters.clone(),
+        )
+    }
 
-        value
-            .as_slice_mut()
-            .unwrap()
-            .iter_mut()
-            .for_each(|x| *x = 100.0 * clamp(*x, min, max));
+    /// Clamp the value of this variable to lie between `min` and `max`,
+    /// passing gradients through unchanged as if no clamping took place.
+    pub fn clamp_straight_through(
+        &self,
+        min: f32,
+        max: f32,
+    ) -> Variable<ElementwiseNode<T, StraightThroughClamp>> {
+        Variable::new(
+            Rc::new(ElementwiseNode::new(
+                Rc::clone(&self.node),
+                StraightThroughClamp(Clamp::new(min, max)),
+            )),
+            self.parameters.clone(),
+        )
     }
 
     /// Square this variable.
@@ -337,6 +357,68 @@ where
         )
     }
 
+    /// Take the mean of all the elements of this variable.
+    pub fn scalar_mean(&self) -> Variable<SumNode<T>> {
+        Variable::new(
+            Rc::new(SumNode::new_mean(Rc::clone(&self.node))),
+            self.parameters.clone(),
+        )
+    }
+
+    /// Sum this variable along the given axis. The reduced axis
+    /// is kept with length 1, so that reducing along `ndarray::Axis(0)`
+    /// returns a row vector, and along `ndarray::Axis(1)` a column vector.
+    pub fn sum_axis(&self, axis: ndarray::Axis) -> Variable<SumAxisNode<T>> {
+        Variable::new(
+            Rc::new(SumAxisNode::new(Rc::clone(&self.node), axis)),
+            self.parameters.clone(),
+        )
+    }
+
+    /// Take the mean of this variable along the given axis. The reduced
+    /// axis is kept with length 1.
+    pub fn mean_axis(&self, axis: ndarray::Axis) -> Variable<SumAxisNode<T>> {
+        Variable::new(
+            Rc::new(SumAxisNode::new_mean(Rc::clone(&self.node), axis)),
+            self.parameters.clone(),
+        )
+    }
+
+    /// Take the maximum of this variable along the given axis. The reduced
+    /// axis is kept with length 1. Gradients are passed only to the maximal elements.
+    pub fn max_axis(&self, axis: ndarray::Axis) -> Variable<ExtremumAxisNode<T>> {
+        Variable::new(
+            Rc::new(ExtremumAxisNode::new(
+                Rc::clone(&self.node),
+                axis,
+                Extremum::Max,
+            )),
+            self.parameters.clone(),
+        )
+    }
+
+    /// Take the minimum of this variable along the given axis. The reduced
+    /// axis is kept with length 1. Gradients are passed only to the minimal elements.
+    pub fn min_axis(&self, axis: ndarray::Axis) -> Variable<ExtremumAxisNode<T>> {
+        Variable::new(
+            Rc::new(ExtremumAxisNode::new(
+                Rc::clone(&self.node),
+                axis,
+                Extremum::Min,
+            )),
+            self.parameters.clone(),
+        )
+    }
+
+    /// Take the log-sum-exp of this variable along the given axis, computed
+    /// without overflow for large values. The reduced axis is kept with length 1.
+    pub fn logsumexp(&self, axis: ndarray::Axis) -> Variable<LogSumExpNode<T>> {
+        Variable::new(
+            Rc::new(LogSumExpNode::new(Rc::clone(&self.node), axis)),
+            self.parameters.clone(),
+        )
+    }
+
     /// Take the natural logarithm of this variable.
     pub fn ln(&self) -> Variable<LogNode<T>> {
         Variable::new(
@@ -361,6 +443,77 @@ where
         )
     }
 
+    /// Reshape this variable into a `(rows, cols)` matrix, reading
+    /// and writing its elements in row-major order.
+    ///
+    /// The result does not share storage with this variable: values
+    /// and gradients are copied on every pass.
+    pub fn reshape(&self, shape: (usize, usize)) -> Variable<ReshapeNode<T>> {
+        Variable::new(
+            Rc::new(ReshapeNode::new(Rc::clone(&self.node), shape)),
+            self.parameters.clone(),
+        )
+    }
+
+    /// Flatten this variable into a single row vector.
+    pub fn flatten(&self) -> Variable<ReshapeNode<T>> {
+        let shape = (1, self.value().len());
+        self.reshape(shape)
+    }
+
+    /// Use the value of this variable, but stop gradients from flowing
+    /// back through it. Parameters only reachable through the returned
+    /// variable are not part of its parameters.
+    pub fn detach(&self) -> Variable<DetachNode<T>> {
+        Variable::new(Rc::new(DetachNode::new(Rc::clone(&self.node))), Vec::new())
+    }
+
+    /// Take elements of this variable where `mask` is non-zero, and elements of
+    /// `other` elsewhere. Gradients only flow to the selected elements, so
+    /// infinities on the unselected side do not produce NaNs.
+    pub fn where_<S>(
+        &self,
+        mask: &Variable<InputNode>,
+        other: &Variable<S>,
+    ) -> Variable<WhereNode<T, S>>
+    where
+        S: Node<Value = Arr, InputGradient = Arr>,
+    {
+        Variable::new(
+            Rc::new(WhereNode::new(
+                Rc::clone(&mask.node),
+                Rc::clone(&self.node),
+                Rc::clone(&other.node),
+            )),
+            merge_parameters(&self.parameters, &other.parameters),
+        )
+    }
+
+    /// Replace elements of this variable with `value` where `mask` is non-zero.
+    pub fn masked_fill(
+        &self,
+        mask: &Variable<InputNode>,
+        value: f32,
+    ) -> Variable<WhereNode<InputNode, T>> {
+        let fill = InputNode::new(Arr::from_elem(self.value().dim(), value));
+        fill.where_(mask, self)
+    }
+
+    /// Apply inverted dropout with drop probability `p`. Acts as the identity
+    /// outside of training mode (see `set_training`).
+    pub fn dropout(&self, p: f32) -> Variable<DropoutNode<T>> {
+        self.dropout_with_rng(p, &mut rand::thread_rng())
+    }
+
+    /// Apply dropout, seeding its mask generator from `rng` for
+    /// reproducible masks.
+    pub fn dropout_with_rng<R: rand::Rng>(&self, p: f32, rng: &mut R) -> Variable<DropoutNode<T>> {
+        Variable::new(
+            Rc::new(DropoutNode::new(Rc::clone(&self.node), p, rng)),
+            self.parameters.clone(),
+        )
+    }
+
     /// Exponentiate this variable.
     pub fn exp(&self) -> Variable<ExpNode<T>> {
         Variable::new(
@@ -369,6 +522,70 @@ where
         )
     }
 
+    /// Take the absolute value of this variable.
+    pub fn abs(&self) -> Variable<ElementwiseNode<T, Abs>> {
+        Variable::new(
+            Rc::new(ElementwiseNode::new(Rc::clone(&self.node), Abs)),
+            self.parameters.clone(),
+        )
+    }
+
+    /// Take the square root of this variable.
+    pub fn sqrt(&self) -> Variable<ElementwiseNode<T, Sqrt>> {
+        Variable::new(
+            Rc::new(ElementwiseNode::new(Rc::clone(&self.node), Sqrt)),
+            self.parameters.clone(),
+        )
+    }
+
+    /// Raise this variable to the power of `exponent`.
+    pub fn powf(&self, exponent: f32) -> Variable<ElementwiseNode<T, Powf>> {
+        Variable::new(
+            Rc::new(ElementwiseNode::new(Rc::clone(&self.node), Powf(exponent))),
+            self.parameters.clone(),
+        )
+    }
+
+    /// Take the reciprocal (`1 / x`) of this variable.
+    pub fn reciprocal(&self) -> Variable<ElementwiseNode<T, Reciprocal>> {
+        Variable::new(
+            Rc::new(ElementwiseNode::new(Rc::clone(&self.node), Reciprocal)),
+            self.parameters.clone(),
+        )
+    }
+
+    /// Take the sine of this variable.
+    pub fn sin(&self) -> Variable<ElementwiseNode<T, Sin>> {
+        Variable::new(
+            Rc::new(ElementwiseNode::new(Rc::clone(&self.node), Sin)),
+            self.parameters.clone(),
+        )
+    }
+
+    /// Take the cosine of this variable.
+    pub fn cos(&self) -> Variable<ElementwiseNode<T, Cos>> {
+        Variable::new(
+            Rc::new(ElementwiseNode::new(Rc::clone(&self.node), Cos)),
+            self.parameters.clone(),
+        )
+    }
+
+    /// Compute the softplus (`ln(1 + e^x)`) of this variable.
+    pub fn softplus(&self) -> Variable<ElementwiseNode<T, Softplus>> {
+        Variable::new(
+            Rc::new(ElementwiseNode::new(Rc::clone(&self.node), Softplus)),
+            self.parameters.clone(),
+        )
+    }
+
+    /// Compute `ln(1 + x)` of this variable, accurately for small `x`.
+    pub fn log1p(&self) -> Variable<ElementwiseNode<T, Log1p>> {
+        Variable::new(
+            Rc::new(ElementwiseNode::new(Rc::clone(&self.node), Log1p)),
+            self.parameters.clone(),
+        )
+    }
+
     /// Compute the softmax of this variable.
     pub fn softmax(&self) -> Variable<SoftmaxNode<T>> {
         Variable::new(
@@ -401,6 +618,123 @@ where
         )
     }
 
+    /// Compute the leaky ReLU of this variable, with slope `alpha` for negative inputs.
+    pub fn leaky_relu(&self, alpha: f32) -> Variable<ElementwiseNode<T, LeakyRelu>> {
+        Variable::new(
+            Rc::new(ElementwiseNode::new(
+                Rc::clone(&self.node),
+                LeakyRelu(alpha),
+            )),
+            self.parameters.clone(),
+        )
+    }
+
+    /// Compute the ELU of this variable, saturating at `-alpha` for negative inputs.
+    pub fn elu(&self, alpha: f32) -> Variable<ElementwiseNode<T, Elu>> {
+        Variable::new(
+            Rc::new(ElementwiseNode::new(Rc::clone(&self.node), Elu(alpha))),
+            self.parameters.clone(),
+        )
+    }
+
+    /// Compute the SELU (scaled ELU) of this variable.
+    pub fn selu(&self) -> Variable<ElementwiseNode<T, Selu>> {
+        Variable::new(
+            Rc::new(ElementwiseNode::new(Rc::clone(&self.node), Selu)),
+            self.parameters.clone(),
+        )
+    }
+
+    /// Compute the exact GELU (`x * Phi(x)`) of this variable.
+    pub fn gelu(&self) -> Variable<ElementwiseNode<T, Gelu>> {
+        Variable::new(
+            Rc::new(ElementwiseNode::new(Rc::clone(&self.node), Gelu)),
+            self.parameters.clone(),
+        )
+    }
+
+    /// Compute the tanh approximation of the GELU of this variable.
+    pub fn gelu_tanh(&self) -> Variable<ElementwiseNode<T, GeluTanh>> {
+        Variable::new(
+            Rc::new(ElementwiseNode::new(Rc::clone(&self.node), GeluTanh)),
+            self.parameters.clone(),
+        )
+    }
+
+    /// Compute the swish (`x * sigmoid(x)`) of this variable.
+    pub fn swish(&self) -> Variable<ElementwiseNode<T, Swish>> {
+        Variable::new(
+            Rc::new(ElementwiseNode::new(Rc::clone(&self.node), Swish)),
+            self.parameters.clone(),
+        )
+    }
+
+    /// Compute the SiLU of this variable. This is the same as `swish`.
+    pub fn silu(&self) -> Variable<ElementwiseNode<T, Swish>> {
+        self.swish()
+    }
+
+    /// Normalise this variable to unit L2 norm along the given axis: with
+    /// `Axis(1)`, every row is normalised.
+    pub fn l2_normalize(&self, axis: ndarray::Axis) -> Variable<L2NormalizeNode<T>> {
+        Variable::new(
+            Rc::new(L2NormalizeNode::new(Rc::clone(&self.node), axis)),
+            self.parameters.clone(),
+        )
+    }
+
+    /// Compute the row-wise cosine similarity of LHS and RHS.
+    pub fn cosine_similarity<S>(
+        &self,
+        other: &Variable<S>,
+    ) -> Variable<VectorDotNode<L2NormalizeNode<T>, L2NormalizeNode<S>>>
+    where
+        S: Node<Value = Arr, InputGradient = Arr>,
+    {
+        let axis = ndarray::Axis(1);
+        self.l2_normalize(axis)
+            .vector_dot(&other.l2_normalize(axis))
+    }
+
+    /// Compute the distances between every row of LHS and every row of RHS.
+    pub fn pairwise_distances<S>(
+        &self,
+        other: &Variable<S>,
+        distance: Distance,
+    ) -> Variable<PairwiseDistanceNode<T, S>>
+    where
+        S: Node<Value = Arr, InputGradient = Arr>,
+    {
+        Variable::new(
+            Rc::new(PairwiseDistanceNode::new(
+                Rc::clone(&self.node),
+                Rc::clone(&other.node),
+                distance,
+            )),
+            merge_parameters(&self.parameters, &other.parameters),
+        )
+    }
+
+    /// Normalise each row of this variable to zero mean and unit variance,
+    /// then scale by `gain` and shift by `bias`, both of shape `(1, columns)`.
+    pub fn layer_norm(
+        &self,
+        gain: &Variable<ParameterNode>,
+        bias: &Variable<ParameterNode>,
+    ) -> Variable<LayerNormNode<T>> {
+        Variable::new(
+            Rc::new(LayerNormNode::new(
+                Rc::clone(&self.node),
+                Rc::clone(&gain.node),
+                Rc::clone(&bias.node),
+            )),
+            merge_parameters(
+                &self.parameters,
+                &merge_parameters(&gain.parameters, &bias.parameters),
+            ),
+        )
+    }
+
     /// Compute the row-wise vector dot product of LHS and RHS.
     pub fn vector_dot<S>(&self, other: &Variable<S>) -> Variable<VectorDotNode<T, S>>
     where
@@ -446,6 +780,108 @@ where
         )
     }
 
+    /// Concatenate any number of variables, either row-wise (`ndarray::Axis(0)`)
+    /// or column-wise (`ndarray::Axis(1)`), in a single node. Use `boxed`
+    /// to concatenate variables of different types.
+    pub fn concat(operands: &[Variable<T>], axis: ndarray::Axis) -> Variable<ConcatNode<T>> {
+        let parameters = operands.iter().fold(Vec::new(), |parameters, operand| {
+            merge_parameters(&parameters, &operand.parameters)
+        });
+
+        Variable::new(
+            Rc::new(ConcatNode::new(
+                operands.iter().map(|x| Rc::clone(&x.node)).collect(),
+                axis,
+            )),
+            parameters,
+        )
+    }
+
+    /// Apply a user-defined operation to any number of variables. Use `boxed`
+    /// to pass variables of different types.
+    pub fn custom<O: CustomOp>(operands: &[Variable<T>], op: O) -> Variable<CustomNode<O>> {
+        let parameters = operands.iter().fold(Vec::new(), |parameters, operand| {
+            merge_parameters(&parameters, &operand.parameters)
+        });
+
+        Variable::new(
+            Rc::new(CustomNode::new(
+                operands
+                    .iter()
+                    .map(|x| Rc::clone(&x.node) as BoxedNode)
+                    .collect(),
+                op,
+            )),
+            parameters,
+        )
+    }
+
+    /// Split this variable into consecutive pieces of the given `sizes` along
+    /// `axis`. This is the inverse of `concat`.
+    pub fn split(&self, sizes: &[usize], axis: ndarray::Axis) -> Vec<Variable<SplitNode<T>>> {
+        SplitNode::new(Rc::clone(&self.node), sizes, axis)
+            .into_iter()
+            .map(|node| Variable::new(Rc::new(node), self.parameters.clone()))
+            .collect()
+    }
+
+    /// Row-wise indexing of this node. Gradients are accumulated sparsely when
+    /// indexing a parameter node, which makes this the building block of
+    /// embedding layers.
+    pub fn index(&self, index: &Variable<IndexInputNode>) -> Variable<IndexNode<T>> {
+        Variable::new(
+            Rc::new(IndexNode::new(
+                Rc::clone(&self.node),
+                Rc::clone(&index.node),
+            )),
+            merge_parameters(&self.parameters, &index.parameters),
+        )
+    }
+
+    /// Add each row of this node into the row of a `(num_rows, cols)` output
+    /// given by the corresponding entry of `index`. This is the inverse of `index`.
+    pub fn scatter_add(
+        &self,
+        index: &Variable<IndexInputNode>,
+        num_rows: usize,
+    ) -> Variable<ScatterAddNode<T>> {
+        Variable::new(
+            Rc::new(ScatterAddNode::new(
+                Rc::clone(&self.node),
+                Rc::clone(&index.node),
+                num_rows,
+            )),
+            merge_parameters(&self.parameters, &index.parameters),
+        )
+    }
+
+    /// Sum the rows of this node that share a segment id. The output has
+    /// one row per segment, and the number of segments is fixed by the
+    /// largest id present when the node is created.
+    pub fn segment_sum(
+        &self,
+        segment_ids: &Variable<IndexInputNode>,
+    ) -> Variable<ScatterAddNode<T>> {
+        let num_segments = num_segments(&segment_ids.value());
+        self.scatter_add(segment_ids, num_segments)
+    }
+
+    /// Average the rows of this node that share a segment id. Segments with
+    /// no rows are zero. See `segment_sum` for the shape of the output.
+    pub fn segment_mean(
+        &self,
+        segment_ids: &Variable<IndexInputNode>,
+    ) -> Variable<ScatterAddNode<T>> {
+        Variable::new(
+            Rc::new(ScatterAddNode::new_mean(
+                Rc::clone(&self.node),
+                Rc::clone(&segment_ids.node),
+                num_segments(&segment_ids.value()),
+            )),
+            merge_parameters(&self.parameters, &segment_ids.parameters),
+        )
+    }
+
     /// Slice the node according to the `ndarray` slice syntax.
     pub fn slice(
         &self,
@@ -464,19 +900,40 @@ impl Variable<ParameterNode> {
         self.node.gradient.borrow().materialized_gradient()
     }
 
+    /// Pool the rows of this parameter node over each bag of indices,
+    /// giving one row per bag. Empty bags give rows of zeros. Gradients
+    /// are accumulated sparsely.
+    pub fn embedding_bag(
+        &self,
+        bags: &Variable<BagInputNode>,
+        pooling: Pooling,
+    ) -> Variable<EmbeddingBagNode> {
+        Variable::new(
+            Rc::new(EmbeddingBagNode::new(
+                Rc::clone(&self.node),
+                Rc::clone(&bags.node),
+                pooling,
+            )),
+            merge_parameters(&self.parameters, &bags.parameters),
+        )
+    }
+
     fn as_ptr(&self) -> *const ParameterNode {
         self.node.deref() as *const ParameterNode
     }
+}
 
-    /// Row-wise indexing of this parameter node. Primiarily used
-    /// to implement embedding layers.
-    pub fn index(&self, index: &Variable<IndexInputNode>) -> Variable<IndexNode<ParameterNode>> {
+impl Variable<SparseInputNode> {
+    /// Multiply this sparse matrix by a dense parameter matrix, without
+    /// materializing the sparse matrix. The gradient of the parameter is
+    /// sparse, touching only the rows for columns with non-zero entries.
+    pub fn sparse_dot(&self, other: &Variable<ParameterNode>) -> Variable<SparseDotNode> {
         Variable::new(
-            Rc::new(IndexNode::new(
+            Rc::new(SparseDotNode::new(
                 Rc::clone(&self.node),
-                Rc::clone(&index.node),
+                Rc::clone(&other.node),
             )),
-            merge_parameters(&self.parameters, &index.parameters),
+            merge_parameters(&self.parameters, &other.parameters),
         )
     }
 }
@@ -522,19 +979,49 @@ impl<'value> DataInput<&'value [usize]> for Variable<IndexInputNode> {
     }
 }
 
-impl DataInput<usize> for Variable<IndexInputNode> {
-    fn set_value(&self, value: usize) {
-        let mut node_value = self.node.value.borrow_mut();
-        node_value.clear();
-        node_value.push(value);
+impl<'value, T: AsRef<[usize]>> DataInput<&'value [T]> for Variable<BagInputNode> {
+    fn set_value(&self, value: &[T]) {
+        self.node.set_indices(value);
     }
 }
 
-macro_rules! impl_arithmetic_op {
-    ($trait:ident, $fn:ident, $node:ident) => {
-        impl<LHS, RHS> $trait<Variable<RHS>> for Variable<LHS>
-        where
-            RHS: Node<Value = Arr, InputGradient = Arr>,
+impl<'value, T, W> DataInput<(&'value [T], &'value [W])> for Variable<BagInputNode>
+where
+    T: AsRef<[usize]>,
+    W: AsRef<[f32]>,
+{
+    fn set_value(&self, value: (&[T], &[W])) {
+        let (bags, weights) = value;
+        self.node.set_indices(bags);
+        self.node.set_weights(weights);
+    }
+}
+
+impl<'value> DataInput<(&'value [usize], &'value [usize], &'value [f32])>
+    for Variable<SparseInputNode>
+{
+    fn set_value(&self, value: (&[usize], &[usize], &[f32])) {
+        let (indptr, indices, values) = value;
+        self.node.set_value(indptr, indices, values);
+    }
+}
+
+impl DataInput<usize> for Variable<IndexInputNode> {
+    fn set_value(&self, value: usize) {
+        let mut node_value = self.node.value.borrow_mut();
+        node_value.clear();
+        node_value.push(value);
+    }
+}
+
+macro_rules! impl_arithmetic_op {
+    ($trait:ident, $fn:ident, $node:ident) => {
+        /// The operands are broadcast against each other following
+        /// numpy semantics, so row vectors, column vectors and scalars
+        /// can be combined with matrices.
+        impl<LHS, RHS> $trait<Variable<RHS>> for Variable<LHS>
+        where
+            RHS: Node<Value = Arr, InputGradient = Arr>,
             LHS: Node<Value = Arr, InputGradient = Arr>,
         {
             type Output = Variable<$node<LHS, RHS>>;
@@ -666,11 +1153,11 @@ pub fn assert_close(x: &Arr, y: &Arr, tol: f32) {
 #[cfg(test)]
 mod tests {
 
-    use ndarray::arr2;
+    use ndarray::{arr2, Axis};
 
     use optim::{Adagrad, Optimizer, SGD};
     use rand::distributions::{Distribution, Uniform};
-    use rand::Rng;
+    use rand::{Rng, SeedableRng};
     use rayon::prelude::*;
     use std::sync::Arc;
 
@@ -687,6 +1174,16 @@ mod tests {
         Uniform::new(0, rows).sample(&mut rand::thread_rng())
     }
 
+    /// Random permutation of evenly spaced values in `[-0.5, 0.5)`, so that
+    /// no two entries are within a finite-difference step of each other.
+    fn distinct_matrix(rows: usize, cols: usize) -> Arr {
+        let size = rows * cols;
+        let mut values: Vec<f32> = (0..size).map(|x| x as f32 / size as f32 - 0.5).collect();
+        rand::thread_rng().shuffle(&mut values);
+
+        Arr::from_shape_vec((rows, cols), values).unwrap()
+    }
+
     #[test]
     fn test_constant_sub() {
         let mut x = ParameterNode::new(Arr::zeros((10, 10)) + 1.0);
@@ -758,6 +1255,87 @@ mod tests {
         assert_close(&finite_difference, &gradient, TOLERANCE);
     }
     #[test]
+    fn add_broadcast_finite_difference() {
+        let mut x = ParameterNode::new(random_matrix(10, 5));
+        let mut row = ParameterNode::new(random_matrix(1, 5));
+        let mut col = ParameterNode::new(random_matrix(10, 1));
+        let mut scalar = ParameterNode::new(random_matrix(1, 1));
+        let z = (row.clone() + x.clone() + col.clone()) + scalar.clone();
+        let mut z = (z.clone() * z.clone().sigmoid()).sigmoid();
+
+        assert_eq!(z.value().shape(), &[10, 5]);
+
+        let (difference, gradient) = finite_difference(&mut x, &mut z);
+        assert_close(&difference, &gradient, TOLERANCE);
+        let (difference, gradient) = finite_difference(&mut row, &mut z);
+        assert_close(&difference, &gradient, TOLERANCE);
+        let (difference, gradient) = finite_difference(&mut col, &mut z);
+        assert_close(&difference, &gradient, TOLERANCE);
+        let (difference, gradient) = finite_difference(&mut scalar, &mut z);
+        assert_close(&difference, &gradient, TOLERANCE);
+    }
+    #[test]
+    fn sub_broadcast_finite_difference() {
+        let mut x = ParameterNode::new(random_matrix(10, 5));
+        let mut row = ParameterNode::new(random_matrix(1, 5));
+        let mut col = ParameterNode::new(random_matrix(10, 1));
+        let z = (row.clone() - x.clone()) - col.clone();
+        let mut z = (z.clone() * z.clone().sigmoid()).sigmoid();
+
+        assert_eq!(z.value().shape(), &[10, 5]);
+
+        let (difference, gradient) = finite_difference(&mut x, &mut z);
+        assert_close(&difference, &gradient, TOLERANCE);
+        let (difference, gradient) = finite_difference(&mut row, &mut z);
+        assert_close(&difference, &gradient, TOLERANCE);
+        let (difference, gradient) = finite_difference(&mut col, &mut z);
+        assert_close(&difference, &gradient, TOLERANCE);
+    }
+    #[test]
+    fn mul_broadcast_finite_difference() {
+        let mut x = ParameterNode::new(random_matrix(10, 5));
+        let mut row = ParameterNode::new(random_matrix(1, 5));
+        let mut col = ParameterNode::new(random_matrix(10, 1));
+        let mut scalar = ParameterNode::new(random_matrix(1, 1));
+        let z = (row.clone() * x.clone() * col.clone()) * scalar.clone();
+        let mut z = (z.clone() * z.clone().sigmoid()).sigmoid();
+
+        assert_eq!(z.value().shape(), &[10, 5]);
+
+        let (difference, gradient) = finite_difference(&mut x, &mut z);
+        assert_close(&difference, &gradient, TOLERANCE);
+        let (difference, gradient) = finite_difference(&mut row, &mut z);
+        assert_close(&difference, &gradient, TOLERANCE);
+        let (difference, gradient) = finite_difference(&mut col, &mut z);
+        assert_close(&difference, &gradient, TOLERANCE);
+        let (difference, gradient) = finite_difference(&mut scalar, &mut z);
+        assert_close(&difference, &gradient, TOLERANCE);
+    }
+    #[test]
+    fn div_broadcast_finite_difference() {
+        let mut x = ParameterNode::new(random_matrix(10, 5));
+        let mut row = ParameterNode::new(random_matrix(1, 5).map(|x| x.abs() + 1.0));
+        let mut col = ParameterNode::new(random_matrix(10, 1).map(|x| x.abs() + 1.0));
+        let z = (x.clone() / row.clone()) + (col.clone() / x.clone().exp());
+        let mut z = (z.clone() * z.clone().sigmoid()).sigmoid();
+
+        assert_eq!(z.value().shape(), &[10, 5]);
+
+        let (difference, gradient) = finite_difference(&mut x, &mut z);
+        assert_close(&difference, &gradient, TOLERANCE);
+        let (difference, gradient) = finite_difference(&mut row, &mut z);
+        assert_close(&difference, &gradient, TOLERANCE);
+        let (difference, gradient) = finite_difference(&mut col, &mut z);
+        assert_close(&difference, &gradient, TOLERANCE);
+    }
+    #[test]
+    #[should_panic]
+    fn broadcast_incompatible_shapes() {
+        let x = ParameterNode::new(random_matrix(10, 5));
+        let y = ParameterNode::new(random_matrix(2, 5));
+        let _ = x + y;
+    }
+    #[test]
     fn vector_dot_finite_difference() {
         let mut x = ParameterNode::new(random_matrix(10, 5));
         let mut y = ParameterNode::new(random_matrix(10, 5));
@@ -828,6 +1406,89 @@ mod tests {
         assert_close(&finite_difference, &gradient, TOLERANCE * 2.0);
     }
     #[test]
+    fn mean_finite_difference() {
+        let mut x = ParameterNode::new(random_matrix(10, 5));
+        let mut z = (x.clone() + x.clone()).square().scalar_mean();
+        let sum = (x.clone() + x.clone()).square().scalar_sum();
+
+        assert_close(z.value().deref(), &(sum.value().deref() / 50.0), 1e-4);
+
+        let (finite_difference, gradient) = finite_difference(&mut x, &mut z);
+        assert_close(&finite_difference, &gradient, TOLERANCE);
+    }
+    #[test]
+    fn sum_axis_finite_difference() {
+        for &axis in &[Axis(0), Axis(1)] {
+            let mut x = ParameterNode::new(random_matrix(10, 5));
+            let mut z = x.square().sum_axis(axis).sigmoid();
+
+            let expected = x
+                .value()
+                .map(|x| x.powi(2))
+                .sum_axis(axis)
+                .insert_axis(axis);
+            assert_close(
+                z.value().deref(),
+                &expected.map(|&x| numerics::sigmoid(x)),
+                1e-4,
+            );
+
+            let (difference, gradient) = finite_difference(&mut x, &mut z);
+            assert_close(&difference, &gradient, TOLERANCE);
+        }
+    }
+    #[test]
+    fn mean_axis_finite_difference() {
+        for &axis in &[Axis(0), Axis(1)] {
+            let mut x = ParameterNode::new(random_matrix(10, 5));
+            let z = x.mean_axis(axis);
+            let mut z = z.clone() * z.clone();
+
+            let (difference, gradient) = finite_difference(&mut x, &mut z);
+            assert_close(&difference, &gradient, TOLERANCE);
+        }
+    }
+    #[test]
+    fn max_axis_finite_difference() {
+        for &axis in &[Axis(0), Axis(1)] {
+            let mut x = ParameterNode::new(distinct_matrix(10, 5));
+            let z = (x.clone() * 2.0).max_axis(axis);
+            let mut z = z.clone() * z.clone();
+
+            let (difference, gradient) = finite_difference(&mut x, &mut z);
+            assert_close(&difference, &gradient, TOLERANCE);
+        }
+    }
+    #[test]
+    fn min_axis_finite_difference() {
+        for &axis in &[Axis(0), Axis(1)] {
+            let mut x = ParameterNode::new(distinct_matrix(10, 5));
+            let z = (x.clone() * 2.0).min_axis(axis);
+            let mut z = z.clone() * z.clone();
+
+            let (difference, gradient) = finite_difference(&mut x, &mut z);
+            assert_close(&difference, &gradient, TOLERANCE);
+        }
+    }
+    #[test]
+    fn max_min_axis_values() {
+        let x = InputNode::new(arr2(&[[1.0, 5.0, -2.0], [3.0, -4.0, 0.5]]));
+
+        assert_eq!(
+            x.max_axis(Axis(0)).value().deref(),
+            &arr2(&[[3.0, 5.0, 0.5]])
+        );
+        assert_eq!(x.max_axis(Axis(1)).value().deref(), &arr2(&[[5.0], [3.0]]));
+        assert_eq!(
+            x.min_axis(Axis(0)).value().deref(),
+            &arr2(&[[1.0, -4.0, -2.0]])
+        );
+        assert_eq!(
+            x.min_axis(Axis(1)).value().deref(),
+            &arr2(&[[-2.0], [-4.0]])
+        );
+    }
+    #[test]
     fn squared_sum_finite_difference() {
         let mut x = ParameterNode::new(random_matrix(10, 5));
         let mut z = x.square().scalar_sum();
@@ -852,6 +1513,214 @@ mod tests {
         assert_close(&finite_difference, &gradient, TOLERANCE);
     }
     #[test]
+    fn abs_finite_difference() {
+        // Keep the inputs away from the kink at zero.
+        let mut x = ParameterNode::new(random_matrix(10, 5).map(|x| x + 0.1 * x.signum()));
+        let mut z = (x.clone() + x.clone()).abs();
+
+        let (finite_difference, gradient) = finite_difference(&mut x, &mut z);
+        assert_close(&finite_difference, &gradient, TOLERANCE);
+    }
+    #[test]
+    fn sqrt_finite_difference() {
+        let mut x = ParameterNode::new(random_matrix(10, 5).map(|x| x.abs() + 0.5));
+        let mut z = (x.clone() + x.clone()).sqrt();
+
+        let (finite_difference, gradient) = finite_difference(&mut x, &mut z);
+        assert_close(&finite_difference, &gradient, TOLERANCE);
+    }
+    #[test]
+    fn powf_finite_difference() {
+        let mut x = ParameterNode::new(random_matrix(10, 5).map(|x| x.abs() + 0.5));
+        let mut z = (x.clone() + x.clone()).powf(1.5);
+
+        let (finite_difference, gradient) = finite_difference(&mut x, &mut z);
+        assert_close(&finite_difference, &gradient, TOLERANCE);
+    }
+    #[test]
+    fn reciprocal_finite_difference() {
+        let mut x = ParameterNode::new(random_matrix(10, 5).map(|x| x.abs() + 1.0));
+        let mut z = (x.clone() + x.clone()).reciprocal();
+
+        let (finite_difference, gradient) = finite_difference(&mut x, &mut z);
+        assert_close(&finite_difference, &gradient, TOLERANCE);
+    }
+    #[test]
+    fn sin_finite_difference() {
+        let mut x = ParameterNode::new(random_matrix(10, 5));
+        let mut z = (x.clone() + x.clone()).sin();
+
+        let (finite_difference, gradient) = finite_difference(&mut x, &mut z);
+        assert_close(&finite_difference, &gradient, TOLERANCE);
+    }
+    #[test]
+    fn cos_finite_difference() {
+        let mut x = ParameterNode::new(random_matrix(10, 5));
+        let mut z = (x.clone() + x.clone()).cos();
+
+        let (finite_difference, gradient) = finite_difference(&mut x, &mut z);
+        assert_close(&finite_difference, &gradient, TOLERANCE);
+    }
+    #[test]
+    fn softplus_finite_difference() {
+        let mut x = ParameterNode::new(random_matrix(10, 5));
+        let mut z = (x.clone() + x.clone()).softplus();
+
+        let (finite_difference, gradient) = finite_difference(&mut x, &mut z);
+        assert_close(&finite_difference, &gradient, TOLERANCE);
+    }
+    #[test]
+    fn log1p_finite_difference() {
+        let mut x = ParameterNode::new(random_matrix(10, 5).map(|x| x.abs()));
+        let mut z = (x.clone() + x.clone()).log1p();
+
+        let (finite_difference, gradient) = finite_difference(&mut x, &mut z);
+        assert_close(&finite_difference, &gradient, TOLERANCE);
+    }
+    #[test]
+    fn leaky_relu_finite_difference() {
+        // Keep the inputs away from the kink at zero.
+        let mut x = ParameterNode::new(random_matrix(10, 5).map(|x| x + 0.1 * x.signum()));
+        let mut z = (x.clone() + x.clone()).leaky_relu(0.1);
+
+        let (finite_difference, gradient) = finite_difference(&mut x, &mut z);
+        assert_close(&finite_difference, &gradient, TOLERANCE);
+    }
+    #[test]
+    fn elu_finite_difference() {
+        // Keep the inputs away from the kink at zero.
+        let mut x = ParameterNode::new(random_matrix(10, 5).map(|x| x + 0.1 * x.signum()));
+        let mut z = (x.clone() + x.clone()).elu(1.5);
+
+        let (finite_difference, gradient) = finite_difference(&mut x, &mut z);
+        assert_close(&finite_difference, &gradient, TOLERANCE);
+    }
+    #[test]
+    fn selu_finite_difference() {
+        let mut x = ParameterNode::new(random_matrix(10, 5));
+        let mut z = (x.clone() + x.clone()).selu();
+
+        let (finite_difference, gradient) = finite_difference(&mut x, &mut z);
+        assert_close(&finite_difference, &gradient, TOLERANCE);
+    }
+    #[test]
+    fn gelu_finite_difference() {
+        let mut x = ParameterNode::new(random_matrix(10, 5));
+        let mut z = (x.clone() + x.clone()).gelu();
+
+        let (finite_difference, gradient) = finite_difference(&mut x, &mut z);
+        assert_close(&finite_difference, &gradient, TOLERANCE);
+    }
+    #[test]
+    fn gelu_tanh_finite_difference() {
+        let mut x = ParameterNode::new(random_matrix(10, 5));
+        let mut z = (x.clone() + x.clone()).gelu_tanh();
+
+        let (finite_difference, gradient) = finite_difference(&mut x, &mut z);
+        assert_close(&finite_difference, &gradient, TOLERANCE);
+    }
+    #[test]
+    fn swish_finite_difference() {
+        let mut x = ParameterNode::new(random_matrix(10, 5));
+        let mut z = (x.clone() + x.clone()).swish();
+
+        let (finite_difference, gradient) = finite_difference(&mut x, &mut z);
+        assert_close(&finite_difference, &gradient, TOLERANCE);
+    }
+    #[test]
+    fn clamp_finite_difference() {
+        // Keep the (doubled) inputs away from the kinks at the bounds.
+        let away_from = |x: f32, kink: f32| {
+            if (x - kink).abs() < 0.05 {
+                kink + 0.05 * (x - kink).signum()
+            } else {
+                x
+            }
+        };
+        let mut x =
+            ParameterNode::new(random_matrix(10, 5).map(|&x| away_from(away_from(x, -0.1), 0.15)));
+        let z = (x.clone() + x.clone()).clamp(-0.2, 0.3);
+        let mut z = z.clone() * z.clone();
+
+        let (finite_difference, gradient) = finite_difference(&mut x, &mut z);
+        assert_close(&finite_difference, &gradient, TOLERANCE);
+    }
+    #[test]
+    fn clamp_values_and_gradients() {
+        let x = ParameterNode::new(arr2(&[[-3.0, -0.5, 0.0, 0.5, 3.0]]));
+
+        let mut z = x.clamp(-1.0, 1.0);
+        z.forward();
+        z.backward(1.0);
+
+        assert_eq!(z.value().deref(), &arr2(&[[-1.0, -0.5, 0.0, 0.5, 1.0]]));
+        assert_eq!(x.gradient(), arr2(&[[0.0, 1.0, 1.0, 1.0, 0.0]]));
+
+        let x = ParameterNode::new(arr2(&[[-3.0, -0.5, 0.0, 0.5, 3.0]]));
+
+        let mut z = x.clamp_straight_through(-1.0, 1.0);
+        z.forward();
+        z.backward(1.0);
+
+        assert_eq!(z.value().deref(), &arr2(&[[-1.0, -0.5, 0.0, 0.5, 1.0]]));
+        assert_eq!(x.gradient(), arr2(&[[1.0, 1.0, 1.0, 1.0, 1.0]]));
+    }
+    #[test]
+    fn activation_values() {
+        let x = InputNode::new(arr2(&[[-3.0, -0.5, 0.0, 0.5, 3.0]]));
+
+        assert_close(
+            x.leaky_relu(0.1).value().deref(),
+            &arr2(&[[-0.3, -0.05, 0.0, 0.5, 3.0]]),
+            TOLERANCE,
+        );
+        assert_close(
+            x.gelu().value().deref(),
+            &arr2(&[[-0.0040496, -0.1542688, 0.0, 0.3457312, 2.9959504]]),
+            1e-4,
+        );
+        assert_close(
+            x.gelu_tanh().value().deref(),
+            x.gelu().value().deref(),
+            1e-3,
+        );
+        assert_close(
+            x.silu().value().deref(),
+            (x.clone() * x.sigmoid()).value().deref(),
+            TOLERANCE,
+        );
+        assert_close(
+            x.selu().value().deref(),
+            (x.elu(1.6732632) * 1.050701).value().deref(),
+            TOLERANCE,
+        );
+    }
+    #[test]
+    fn elementwise_values() {
+        let x = InputNode::new(arr2(&[[-2.0, 0.0, 0.5, 30.0, -30.0]]));
+
+        assert_close(
+            x.abs().value().deref(),
+            &arr2(&[[2.0, 0.0, 0.5, 30.0, 30.0]]),
+            TOLERANCE,
+        );
+        assert_close(
+            x.softplus().value().deref(),
+            &x.value().map(|&x| (1.0 + (x as f64).exp()).ln() as f32),
+            TOLERANCE,
+        );
+        assert_close(
+            x.abs().powf(2.0).value().deref(),
+            x.square().value().deref(),
+            TOLERANCE,
+        );
+        assert_close(
+            (x.sin().square() + x.cos().square()).value().deref(),
+            &Arr::ones((1, 5)),
+            TOLERANCE,
+        );
+    }
+    #[test]
     fn dot_square_finite_difference() {
         let mut x = ParameterNode::new(random_matrix(10, 5));
         let y = ParameterNode::new(random_matrix(10, 5));
@@ -906,6 +1775,36 @@ mod tests {
         assert_close(&finite_difference, &gradient, TOLERANCE);
     }
     #[test]
+    fn minibatch_softmax_finite_difference() {
+        let mut x = ParameterNode::new(random_matrix(5, 10));
+        let mut z = (x.clone() + x.clone()).softmax();
+
+        for row in z.value().genrows() {
+            assert!((row.scalar_sum() - 1.0).abs() < 1e-4);
+        }
+
+        let (difference, gradient) = finite_difference(&mut x, &mut z);
+        assert_close(&difference, &gradient, TOLERANCE);
+
+        let mut z = z.clone() * z.clone();
+
+        let (difference, gradient) = finite_difference(&mut x, &mut z);
+        assert_close(&difference, &gradient, TOLERANCE);
+    }
+    #[test]
+    fn minibatch_log_softmax_finite_difference() {
+        let mut x = ParameterNode::new(random_matrix(5, 10));
+        let z = (x.clone() + x.clone()).log_softmax();
+        let v = (x.clone() + x.clone()).softmax().ln();
+
+        assert_close(v.value().deref(), z.value().deref(), TOLERANCE);
+
+        let mut z = z.clone() * z.clone().exp();
+
+        let (difference, gradient) = finite_difference(&mut x, &mut z);
+        assert_close(&difference, &gradient, TOLERANCE);
+    }
+    #[test]
     fn sparse_categorical_cross_entropy_finite_difference() {
         let mut x = ParameterNode::new(random_matrix(1, 10));
         let z = x.clone() + x.clone();
@@ -916,21 +1815,90 @@ mod tests {
         assert_close(&finite_difference, &gradient, TOLERANCE);
     }
     #[test]
-    fn rowwise_stack_finite_difference() {
-        let mut x = ParameterNode::new(random_matrix(10, 5));
-        let mut y = ParameterNode::new(random_matrix(10, 5));
-        //let v = x.clone() + y.clone();
+    fn minibatch_sparse_categorical_cross_entropy_finite_difference() {
+        let mut x = ParameterNode::new(random_matrix(4, 10));
+        let z = x.clone() + x.clone();
+        let idx = IndexInputNode::new(&vec![0, 3, 9, 3][..]);
+        let mut loss = nn::losses::sparse_categorical_crossentropy(&z, &idx);
 
-        let z = x.stack(&y, ndarray::Axis(0));
-        let mut z = z.clone().sigmoid() * z.clone().relu();
+        assert_eq!(loss.value().dim(), (1, 1));
+        assert_eq!(loss.predictions().dim(), (4, 10));
 
-        assert_eq!(z.value().rows(), 20);
-        assert_eq!(z.value().cols(), 5);
+        let (finite_difference, gradient) = finite_difference(&mut x, &mut loss);
+        assert_close(&finite_difference, &gradient, TOLERANCE);
+    }
+    #[test]
+    fn per_row_sparse_categorical_cross_entropy_finite_difference() {
+        let mut x = ParameterNode::new(random_matrix(4, 10));
+        let z = x.clone() + x.clone();
+        let idx = IndexInputNode::new(&vec![0, 3, 9, 3][..]);
+        let mut loss = nn::losses::sparse_categorical_crossentropy_per_row(&z, &idx);
+        let summed_loss = nn::losses::sparse_categorical_crossentropy(&z, &idx);
 
-        let (difference, gradient) = finite_difference(&mut x, &mut z);
+        assert_eq!(loss.value().dim(), (4, 1));
+        assert!((loss.value().scalar_sum() - summed_loss.value()[(0, 0)]).abs() < 1e-4);
+
+        for (row, &target) in [0, 3, 9, 3].iter().enumerate() {
+            assert_eq!(loss.value()[(row, 0)], -loss.predictions()[(row, target)]);
+        }
+
+        let (finite_difference, gradient) = finite_difference(&mut x, &mut loss);
+        assert_close(&finite_difference, &gradient, TOLERANCE);
+    }
+    #[test]
+    fn weighted_sparse_categorical_cross_entropy_finite_difference() {
+        let mut x = ParameterNode::new(random_matrix(4, 10));
+        let z = x.clone() + x.clone();
+        let idx = IndexInputNode::new(&vec![0, 3, 9, 3][..]);
+        let loss = nn::losses::sparse_categorical_crossentropy(&z, &idx);
+        let mut weighted_loss = loss * 0.3;
+
+        let (difference, gradient) = finite_difference(&mut x, &mut weighted_loss);
         assert_close(&difference, &gradient, TOLERANCE);
 
-        let (difference, gradient) = finite_difference(&mut y, &mut z);
+        let weights = InputNode::new(arr2(&[[0.5], [2.0], [0.0], [1.5]]));
+        let loss = nn::losses::sparse_categorical_crossentropy_per_row(&z, &idx);
+        let mut weighted_loss = (loss * weights).scalar_sum();
+
+        let (difference, gradient) = finite_difference(&mut x, &mut weighted_loss);
+        assert_close(&difference, &gradient, TOLERANCE);
+    }
+    #[test]
+    fn summed_sparse_categorical_cross_entropy_finite_difference() {
+        let mut x = ParameterNode::new(random_matrix(1, 10));
+        let z = x.clone() + x.clone();
+        let first_idx = IndexInputNode::new(&vec![0][..]);
+        let second_idx = IndexInputNode::new(&vec![4, 7][..]);
+        let first_loss = nn::losses::sparse_categorical_crossentropy(&z, &first_idx);
+        let second_loss = nn::losses::sparse_categorical_crossentropy(&z, &second_idx);
+        let mut loss = first_loss * 0.7 + second_loss * 2.0;
+
+        let (finite_difference, gradient) = finite_difference(&mut x, &mut loss);
+        assert_close(&finite_difference, &gradient, TOLERANCE);
+    }
+    #[test]
+    #[should_panic]
+    fn minibatch_sparse_categorical_cross_entropy_target_mismatch() {
+        let x = ParameterNode::new(random_matrix(4, 10));
+        let idx = IndexInputNode::new(&vec![0, 3][..]);
+        nn::losses::sparse_categorical_crossentropy(&x, &idx);
+    }
+    #[test]
+    fn rowwise_stack_finite_difference() {
+        let mut x = ParameterNode::new(random_matrix(10, 5));
+        let mut y = ParameterNode::new(random_matrix(10, 5));
+        //let v = x.clone() + y.clone();
+
+        let z = x.stack(&y, ndarray::Axis(0));
+        let mut z = z.clone().sigmoid() * z.clone().relu();
+
+        assert_eq!(z.value().rows(), 20);
+        assert_eq!(z.value().cols(), 5);
+
+        let (difference, gradient) = finite_difference(&mut x, &mut z);
+        assert_close(&difference, &gradient, TOLERANCE);
+
+        let (difference, gradient) = finite_difference(&mut y, &mut z);
         assert_close(&difference, &gradient, TOLERANCE);
     }
     #[test]
@@ -971,6 +1939,97 @@ mod tests {
         assert_close(&difference, &gradient, TOLERANCE);
     }
     #[test]
+    fn concat_finite_difference() {
+        // Keep the inputs away from the relu kink at zero.
+        let mut x = ParameterNode::new(random_matrix(3, 5).map(|x| x + 0.1 * x.signum()));
+        let mut y = ParameterNode::new(random_matrix(4, 5).map(|x| x + 0.1 * x.signum()));
+
+        let operands = [
+            x.boxed(),
+            y.tanh().boxed(),
+            x.sigmoid().boxed(),
+            (x.clone() * 2.0).boxed(),
+        ];
+        let z = Variable::concat(&operands, ndarray::Axis(0));
+
+        assert_eq!(z.value().dim(), (13, 5));
+        assert_eq!(z.parameters().len(), 2);
+
+        let mut z = z.clone().sigmoid() * z.clone().relu();
+
+        let (difference, gradient) = finite_difference(&mut x, &mut z);
+        assert_close(&difference, &gradient, TOLERANCE);
+
+        let (difference, gradient) = finite_difference(&mut y, &mut z);
+        assert_close(&difference, &gradient, TOLERANCE);
+
+        let z = Variable::concat(&[x.clone(), x.clone(), x.clone()], ndarray::Axis(1));
+
+        assert_eq!(z.value().dim(), (3, 15));
+
+        let mut z = z.sigmoid();
+
+        let (difference, gradient) = finite_difference(&mut x, &mut z);
+        assert_close(&difference, &gradient, TOLERANCE);
+    }
+    #[test]
+    fn reshape_finite_difference() {
+        let mut x = ParameterNode::new(random_matrix(4, 6));
+        let y = ParameterNode::new(random_matrix(3, 3));
+
+        let z = x.reshape((8, 3));
+
+        assert_eq!(z.value().dim(), (8, 3));
+        assert_eq!(z.value().as_slice(), x.value().as_slice());
+
+        let mut z = (z.dot(&y).sigmoid() + z.clone())
+            .flatten()
+            .reshape((3, 8))
+            .tanh();
+
+        let (difference, gradient) = finite_difference(&mut x, &mut z);
+        assert_close(&difference, &gradient, TOLERANCE);
+
+        let mut z = x.flatten() * x.flatten().square();
+
+        assert_eq!(z.value().dim(), (1, 24));
+
+        let (difference, gradient) = finite_difference(&mut x, &mut z);
+        assert_close(&difference, &gradient, TOLERANCE);
+    }
+    #[test]
+    #[should_panic]
+    fn reshape_incompatible_shape() {
+        let x = ParameterNode::new(random_matrix(4, 6));
+        x.reshape((5, 5));
+    }
+    #[test]
+    fn split_finite_difference() {
+        let mut x = ParameterNode::new(random_matrix(10, 30));
+
+        let pieces = x.split(&[10, 5, 15], ndarray::Axis(1));
+
+        assert_eq!(pieces[0].value().dim(), (10, 10));
+        assert_eq!(pieces[1].value().dim(), (10, 5));
+        assert_eq!(pieces[2].value().dim(), (10, 15));
+
+        let joined = Variable::concat(&pieces, ndarray::Axis(1));
+        assert_eq!(joined.value().deref(), x.value().deref());
+
+        // Leave the middle piece out of the graph.
+        let mut z = (pieces[0].clone() * pieces[0].clone().sigmoid()).scalar_sum()
+            + pieces[2].tanh().scalar_sum();
+
+        let (difference, gradient) = finite_difference(&mut x, &mut z);
+        assert_close(&difference, &gradient, TOLERANCE);
+
+        let pieces = x.split(&[4, 6], ndarray::Axis(0));
+        let mut z = (pieces[0].t().dot(&pieces[0]) + pieces[1].t().dot(&pieces[1])).sigmoid();
+
+        let (difference, gradient) = finite_difference(&mut x, &mut z);
+        assert_close(&difference, &gradient, TOLERANCE);
+    }
+    #[test]
     fn sparse_index_finite_difference() {
         let mut x = ParameterNode::new(random_matrix(100, 5));
 
@@ -985,6 +2044,493 @@ mod tests {
         }
     }
     #[test]
+    fn dense_index_finite_difference() {
+        let mut x = ParameterNode::new(random_matrix(10, 5));
+        let y = ParameterNode::new(random_matrix(5, 5));
+        let hidden = x.dot(&y).tanh();
+
+        let idx = IndexInputNode::new(&[3, 0, 3, 9]);
+        let rows = hidden.index(&idx);
+
+        assert_eq!(rows.value().dim(), (4, 5));
+        assert_eq!(
+            rows.value().subview(Axis(0), 2),
+            hidden.value().subview(Axis(0), 3)
+        );
+
+        let mut z = (rows.clone() * rows.sigmoid() + hidden.index(&idx)).square();
+
+        let (difference, gradient) = finite_difference(&mut x, &mut z);
+        assert_close(&difference, &gradient, TOLERANCE);
+    }
+    #[test]
+    fn scatter_add_finite_difference() {
+        let mut x = ParameterNode::new(random_matrix(6, 5));
+        let idx = IndexInputNode::new(&[2, 0, 2, 3, 0, 2]);
+
+        let z = x.scatter_add(&idx, 5);
+
+        assert_eq!(z.value().dim(), (5, 5));
+        assert_close(
+            &z.value()
+                .subview(Axis(0), 0)
+                .insert_axis(Axis(0))
+                .to_owned(),
+            &(x.value().subview(Axis(0), 1).to_owned() + x.value().subview(Axis(0), 4))
+                .insert_axis(Axis(0)),
+            TOLERANCE,
+        );
+        assert!(z.value().subview(Axis(0), 4).iter().all(|&x| x == 0.0));
+
+        let mut z = (z.clone() * z.sigmoid()).square();
+
+        let (difference, gradient) = finite_difference(&mut x, &mut z);
+        assert_close(&difference, &gradient, TOLERANCE);
+
+        let mut z = x.index(&idx).scatter_add(&idx, 4).tanh();
+
+        let (difference, gradient) = finite_difference(&mut x, &mut z);
+        assert_close(&difference, &gradient, TOLERANCE);
+    }
+    #[test]
+    fn segment_sum_mean_finite_difference() {
+        let mut x = ParameterNode::new(random_matrix(6, 5));
+        let segment_ids = IndexInputNode::new(&[0, 0, 0, 2, 3, 3]);
+
+        let sum = x.segment_sum(&segment_ids);
+        let mean = x.segment_mean(&segment_ids);
+
+        assert_eq!(sum.value().dim(), (4, 5));
+        assert_eq!(mean.value().dim(), (4, 5));
+        assert_close(
+            &(mean.value().slice(s![0..1, ..]).to_owned() * 3.0),
+            &sum.value().slice(s![0..1, ..]).to_owned(),
+            TOLERANCE,
+        );
+        assert!(mean.value().subview(Axis(0), 1).iter().all(|&x| x == 0.0));
+
+        let mut z = (sum.clone() * mean.clone()).tanh();
+
+        let (difference, gradient) = finite_difference(&mut x, &mut z);
+        assert_close(&difference, &gradient, TOLERANCE);
+
+        let mut z = mean.sigmoid();
+
+        let (difference, gradient) = finite_difference(&mut x, &mut z);
+        assert_close(&difference, &gradient, TOLERANCE);
+    }
+    #[test]
+    fn embedding_bag_finite_difference() {
+        let mut x = ParameterNode::new(random_matrix(10, 5));
+        let bags = BagInputNode::new(&[vec![1, 3, 3], vec![], vec![9], vec![0, 2, 4, 6]]);
+        let weighted_bags = BagInputNode::weighted(
+            &[vec![1, 3, 3], vec![5, 7]],
+            &[vec![0.5, 1.0, -2.0], vec![1.5, 0.3]],
+        );
+
+        for &pooling in &[Pooling::Sum, Pooling::Mean, Pooling::Max] {
+            let z = x.embedding_bag(&bags, pooling);
+
+            assert_eq!(z.value().dim(), (4, 5));
+            assert!(z.value().subview(Axis(0), 1).iter().all(|&x| x == 0.0));
+
+            let mut z = (z.clone() * z.sigmoid()).tanh();
+
+            let (difference, gradient) = finite_difference(&mut x, &mut z);
+            assert_close(&difference, &gradient, TOLERANCE);
+
+            let mut z = x.embedding_bag(&weighted_bags, pooling).sigmoid();
+
+            let (difference, gradient) = finite_difference(&mut x, &mut z);
+            assert_close(&difference, &gradient, TOLERANCE);
+        }
+    }
+    #[test]
+    fn embedding_bag_values() {
+        let x = ParameterNode::new(random_matrix(10, 5));
+        let bags = BagInputNode::new(&[vec![1, 3, 3], vec![9]]);
+
+        let sum = x.embedding_bag(&bags, Pooling::Sum);
+        let mean = x.embedding_bag(&bags, Pooling::Mean);
+        let max = x.embedding_bag(&bags, Pooling::Max);
+        let rows = x.index(&IndexInputNode::new(&[1, 3, 3]));
+
+        assert_close(
+            &sum.value().slice(s![0..1, ..]).to_owned(),
+            rows.sum_axis(Axis(0)).value().deref(),
+            TOLERANCE,
+        );
+        assert_close(
+            &mean.value().slice(s![0..1, ..]).to_owned(),
+            rows.mean_axis(Axis(0)).value().deref(),
+            TOLERANCE,
+        );
+        assert_close(
+            &max.value().slice(s![0..1, ..]).to_owned(),
+            rows.max_axis(Axis(0)).value().deref(),
+            TOLERANCE,
+        );
+
+        bags.set_value(&[vec![9], vec![2]][..]);
+        sum.forward();
+
+        assert_eq!(
+            sum.value().subview(Axis(0), 1),
+            x.value().subview(Axis(0), 2)
+        );
+    }
+    #[test]
+    fn empty_bags_default() {
+        assert!(Bags::default().is_empty());
+    }
+    #[test]
+    fn sparse_dot_finite_difference() {
+        let mut x = ParameterNode::new(random_matrix(8, 5));
+        let sparse = SparseInputNode::new(
+            (3, 8),
+            &[0, 3, 3, 6],
+            &[1, 4, 1, 7, 0, 4],
+            &[0.5, -1.0, 2.0, 1.5, 0.3, 0.7],
+        );
+
+        let z = sparse.sparse_dot(&x);
+        let dense = InputNode::new(sparse.value().to_dense()).dot(&x);
+
+        assert_eq!(z.value().dim(), (3, 5));
+        assert_close(z.value().deref(), dense.value().deref(), TOLERANCE);
+
+        let mut z = (z.clone() * z.sigmoid()).tanh();
+
+        let (difference, gradient) = finite_difference(&mut x, &mut z);
+        assert_close(&difference, &gradient, TOLERANCE);
+
+        sparse.set_value((
+            &[0, 1, 2, 4][..],
+            &[2, 3, 2, 5][..],
+            &[1.0, -1.0, 0.5, 2.0][..],
+        ));
+
+        let (difference, gradient) = finite_difference(&mut x, &mut z);
+        assert_close(&difference, &gradient, TOLERANCE);
+
+        // Only the rows of columns with non-zero entries get gradients.
+        assert!(x.gradient().subview(Axis(0), 0).iter().all(|&x| x == 0.0));
+    }
+    #[test]
+    fn detach_finite_difference() {
+        let x = ParameterNode::new(random_matrix(10, 5));
+        let mut y = ParameterNode::new(random_matrix(10, 5));
+
+        let mut z = (x.tanh().detach() * y.clone()).sigmoid();
+
+        assert_eq!(z.parameters().len(), 1);
+
+        let (difference, gradient) = finite_difference(&mut y, &mut z);
+        assert_close(&difference, &gradient, TOLERANCE);
+
+        z.zero_gradient();
+        x.zero_gradient();
+        z.forward();
+        z.backward(1.0);
+
+        assert!(x.gradient().iter().all(|&x| x == 0.0));
+
+        // The detached subgraph is shared with a differentiable path,
+        // so only the latter contributes to the gradient.
+        let activation = x.sigmoid();
+        let mut z = (activation.detach() * activation.clone()).scalar_sum();
+
+        z.zero_gradient();
+        z.forward();
+        z.backward(1.0);
+
+        let expected = activation.value().map(|&x| x * x * (1.0 - x));
+        assert_close(&x.gradient(), &expected, TOLERANCE);
+
+        // Values keep updating across passes.
+        x.set_value(&(x.value().deref() * 0.0));
+        z.forward();
+        assert_close(z.value().deref(), &arr2(&[[0.25 * 50.0]]), TOLERANCE);
+    }
+    #[test]
+    fn where_finite_difference() {
+        let mut x = ParameterNode::new(random_matrix(10, 5));
+        let mut y = ParameterNode::new(random_matrix(10, 5));
+        let mask = InputNode::new(random_matrix(10, 5).map(|&x| (x > 0.0) as u8 as f32));
+
+        let mut z = x.sigmoid().where_(&mask, &y.tanh()) * x.clone();
+
+        assert_eq!(z.parameters().len(), 2);
+
+        let (difference, gradient) = finite_difference(&mut x, &mut z);
+        assert_close(&difference, &gradient, TOLERANCE);
+
+        let (difference, gradient) = finite_difference(&mut y, &mut z);
+        assert_close(&difference, &gradient, TOLERANCE);
+
+        for (&mask, &grad) in mask.value().iter().zip(y.gradient().iter()) {
+            if mask != 0.0 {
+                assert_eq!(grad, 0.0);
+            }
+        }
+    }
+    #[test]
+    fn masked_fill_infinity() {
+        let mut x = ParameterNode::new(random_matrix(4, 4));
+        let mask = InputNode::new(Arr::from_shape_fn((4, 4), |(i, j)| (j > i) as u8 as f32));
+
+        let mut z = x.masked_fill(&mask, std::f32::NEG_INFINITY).softmax();
+
+        let (difference, gradient) = finite_difference(&mut x, &mut z);
+        assert_close(&difference, &gradient, TOLERANCE);
+
+        z.zero_gradient();
+        z.forward();
+        z.backward(1.0);
+
+        assert!(z.value().iter().all(|x| x.is_finite()));
+        assert!(x.gradient().iter().all(|x| x.is_finite()));
+        assert_eq!(z.value()[(0, 0)], 1.0);
+        assert_eq!(z.value()[(0, 1)], 0.0);
+    }
+    #[test]
+    fn dropout() {
+        let x = ParameterNode::new(random_matrix(100, 100) + 2.0);
+        let mut z = x.dropout(0.3).scalar_sum();
+
+        z.forward();
+        z.backward(1.0);
+
+        // Dropped elements get no gradient, kept ones are rescaled.
+        let mut dropped = 0;
+        for &grad in x.gradient().iter() {
+            if grad == 0.0 {
+                dropped += 1;
+            } else {
+                assert!((grad - 1.0 / 0.7).abs() < TOLERANCE);
+            }
+        }
+        assert!((dropped as f32 / 10_000.0 - 0.3).abs() < 0.05);
+
+        // Masks are resampled on every pass.
+        let first = x.gradient();
+        z.zero_gradient();
+        z.forward();
+        z.backward(1.0);
+        assert!(first != x.gradient());
+
+        // Identical seeds give identical masks.
+        let seeded = |seed| {
+            let mut rng = rand::XorShiftRng::from_seed([seed; 16]);
+            x.dropout_with_rng(0.5, &mut rng).value().clone()
+        };
+        assert_eq!(seeded(1), seeded(1));
+        assert!(seeded(1) != seeded(2));
+
+        // Identity in evaluation mode.
+        set_training(false);
+        z.forward();
+        set_training(true);
+        assert_close(z.value().deref(), &arr2(&[[x.value().scalar_sum()]]), 1e-2);
+    }
+    #[test]
+    fn l2_normalize_finite_difference() {
+        for &axis in &[Axis(0), Axis(1)] {
+            let mut x = ParameterNode::new(random_matrix(10, 5));
+            let mut z = (x.l2_normalize(axis) * x.clone()).sigmoid();
+
+            let norms = x.l2_normalize(axis).square().sum_axis(axis);
+            assert!(norms.value().iter().all(|&x| (x - 1.0).abs() < 1e-4));
+
+            let (difference, gradient) = finite_difference(&mut x, &mut z);
+            assert_close(&difference, &gradient, TOLERANCE);
+        }
+
+        // Zero vectors stay finite.
+        let x = ParameterNode::new(Arr::zeros((2, 3)));
+        let mut z = x.l2_normalize(Axis(1)).scalar_sum();
+        z.forward();
+        z.backward(1.0);
+
+        assert!(z.value().iter().all(|x| x.is_finite()));
+        assert!(x.gradient().iter().all(|x| x.is_finite()));
+    }
+    #[test]
+    fn cosine_similarity_finite_difference() {
+        let mut x = ParameterNode::new(random_matrix(10, 5));
+        let mut y = ParameterNode::new(random_matrix(10, 5));
+        let mut z = x.cosine_similarity(&y).sigmoid();
+
+        let (difference, gradient) = finite_difference(&mut x, &mut z);
+        assert_close(&difference, &gradient, TOLERANCE);
+
+        let (difference, gradient) = finite_difference(&mut y, &mut z);
+        assert_close(&difference, &gradient, TOLERANCE);
+
+        let z = x.cosine_similarity(&(x.clone() * 3.0));
+        assert!(z.value().iter().all(|&x| (x - 1.0).abs() < 1e-4));
+    }
+    #[test]
+    fn pairwise_distances_finite_difference() {
+        for &distance in &[Distance::SquaredEuclidean, Distance::Cosine] {
+            let mut x = ParameterNode::new(random_matrix(6, 5));
+            let mut y = ParameterNode::new(random_matrix(4, 5));
+            let mut z = x.pairwise_distances(&y, distance).sigmoid();
+
+            assert_eq!(z.value().dim(), (6, 4));
+
+            let (difference, gradient) = finite_difference(&mut x, &mut z);
+            assert_close(&difference, &gradient, TOLERANCE);
+
+            let (difference, gradient) = finite_difference(&mut y, &mut z);
+            assert_close(&difference, &gradient, TOLERANCE);
+
+            let x_value = x.value();
+            let y_value = y.value();
+            let (first, second) = (x_value.row(2), y_value.row(3));
+            let expected = match distance {
+                Distance::SquaredEuclidean => (&first - &second).map(|x| x * x).scalar_sum(),
+                Distance::Cosine => {
+                    1.0 - first.dot(&second) / (first.dot(&first) * second.dot(&second)).sqrt()
+                }
+            };
+
+            let z = x.pairwise_distances(&y, distance);
+            assert!((z.value()[(2, 3)] - expected).abs() < 1e-4);
+        }
+    }
+    #[test]
+    fn logsumexp_finite_difference() {
+        for &axis in &[Axis(0), Axis(1)] {
+            let mut x = ParameterNode::new(random_matrix(10, 5));
+            let mut z = (x.logsumexp(axis) * 2.0).sigmoid();
+
+            let expected = x.value().map(|x| x.exp()).sum_axis(axis).insert_axis(axis);
+            assert_close(
+                x.logsumexp(axis).value().deref(),
+                &expected.map(|x| x.ln()),
+                1e-4,
+            );
+
+            let (difference, gradient) = finite_difference(&mut x, &mut z);
+            assert_close(&difference, &gradient, TOLERANCE);
+        }
+
+        // Large logits do not overflow.
+        let x = ParameterNode::new(arr2(&[[1000.0, 1000.0], [-1000.0, std::f32::NEG_INFINITY]]));
+        let mut z = x.logsumexp(Axis(1)).scalar_sum();
+
+        z.forward();
+        z.backward(1.0);
+
+        assert_close(
+            x.logsumexp(Axis(1)).value().deref(),
+            &arr2(&[[1000.0 + 2.0f32.ln()], [-1000.0]]),
+            1e-4,
+        );
+        assert_close(&x.gradient(), &arr2(&[[0.5, 0.5], [1.0, 0.0]]), 1e-4);
+    }
+    #[test]
+    fn layer_norm_finite_difference() {
+        // Spread out each row, so that none has a near-zero variance.
+        let spread = Arr::from_shape_fn((10, 5), |(_, col)| col as f32 * 0.5);
+        let mut x = ParameterNode::new(spread + random_matrix(10, 5) * 0.1);
+        let mut gain = ParameterNode::new(random_matrix(1, 5));
+        let mut bias = ParameterNode::new(random_matrix(1, 5));
+
+        let normalized = x.layer_norm(&gain, &bias);
+        let mut z = (normalized.clone() * normalized.clone()).sigmoid();
+
+        assert_eq!(z.parameters().len(), 3);
+
+        let (difference, gradient) = finite_difference(&mut x, &mut z);
+        assert_close(&difference, &gradient, TOLERANCE);
+
+        let (difference, gradient) = finite_difference(&mut gain, &mut z);
+        assert_close(&difference, &gradient, TOLERANCE);
+
+        let (difference, gradient) = finite_difference(&mut bias, &mut z);
+        assert_close(&difference, &gradient, TOLERANCE);
+
+        // With unit gain and zero bias, rows have zero mean and unit variance.
+        let z = x.layer_norm(
+            &ParameterNode::new(Arr::ones((1, 5))),
+            &ParameterNode::new(Arr::zeros((1, 5))),
+        );
+        let means = z.value().mean_axis(Axis(1));
+        let variances = z.value().map(|x| x * x).mean_axis(Axis(1));
+
+        assert!(means.iter().all(|x| x.abs() < 1e-4));
+        assert!(variances.iter().all(|x| (x - 1.0).abs() < 1e-2));
+    }
+    #[test]
+    fn custom_op_finite_difference() {
+        /// Computes `scale * x * tanh(y)`.
+        #[derive(Debug)]
+        struct ScaledTanhProduct {
+            scale: f32,
+        }
+
+        impl CustomOp for ScaledTanhProduct {
+            fn forward(&self, inputs: &[&Arr]) -> Arr {
+                inputs[0] * &inputs[1].map(|y| y.tanh()) * self.scale
+            }
+            fn backward(&self, inputs: &[&Arr], _: &Arr, gradient: &Arr) -> Vec<Arr> {
+                let (x, y) = (inputs[0], inputs[1]);
+
+                vec![
+                    gradient * &y.map(|y| y.tanh()) * self.scale,
+                    gradient * x * &y.map(|y| 1.0 - y.tanh().powi(2)) * self.scale,
+                ]
+            }
+        }
+
+        let mut x = ParameterNode::new(random_matrix(10, 5));
+        let mut y = ParameterNode::new(random_matrix(10, 5));
+
+        let operands = [x.boxed(), y.sigmoid().boxed()];
+        let z = Variable::custom(&operands, ScaledTanhProduct { scale: 2.0 });
+
+        assert_eq!(z.parameters().len(), 2);
+
+        // Reuse the custom node to exercise gradient accumulation.
+        let mut z = (z.clone() + z.clone() * x.clone()).sigmoid();
+
+        let (difference, gradient) = finite_difference(&mut x, &mut z);
+        assert_close(&difference, &gradient, TOLERANCE);
+
+        let (difference, gradient) = finite_difference(&mut y, &mut z);
+        assert_close(&difference, &gradient, TOLERANCE);
+    }
+    #[test]
+    fn custom_op_non_standard_layout() {
+        /// Transposes its input, returning column-major arrays.
+        #[derive(Debug)]
+        struct Transpose;
+
+        impl CustomOp for Transpose {
+            fn forward(&self, inputs: &[&Arr]) -> Arr {
+                inputs[0].t().to_owned()
+            }
+            fn backward(&self, _: &[&Arr], _: &Arr, gradient: &Arr) -> Vec<Arr> {
+                vec![gradient.t().to_owned()]
+            }
+        }
+
+        let mut x = ParameterNode::new(random_matrix(10, 5));
+        let z = Variable::custom(&[x.clone()], Transpose);
+
+        assert_eq!(z.value().deref(), &x.value().t());
+
+        // Multiplying by a fixed matrix gives each element its own
+        // gradient, and reusing the node accumulates them.
+        let weights = InputNode::new(random_matrix(5, 10));
+        let mut z = (z.clone() * weights.clone() + z.clone()).sigmoid();
+
+        let (difference, gradient) = finite_difference(&mut x, &mut z);
+        assert_close(&difference, &gradient, TOLERANCE);
+    }
+    #[test]
     fn univariate_regression() {
         let slope = ParameterNode::new(random_matrix(1, 1));
         let intercept = ParameterNode::new(random_matrix(1, 1));
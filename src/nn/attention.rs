@@ -0,0 +1,304 @@
+//! Module for attention layers.
+//!
+//! Scaled dot-product attention is available as a free function
+//! operating on query, key, and value variables, each with one
+//! position per row. For multi-head attention, first initialize
+//! its parameters, then apply it to your inputs:
+//!
+//! ```rust
+//! # extern crate rand;
+//! # extern crate wyrm;
+//! # use wyrm::InputNode;
+//! # use wyrm::nn::attention;
+//! # use wyrm::nn::xavier_normal;
+//! # fn main() {
+//! let sequence_length = 6;
+//! let dim = 8;
+//! let num_heads = 2;
+//!
+//! // Initialize the parameters.
+//! let parameters = attention::Parameters::new(dim, num_heads, &mut rand::thread_rng());
+//! let attention = parameters.build();
+//!
+//! // Self-attention over the sequence, where positions cannot
+//! // attend to positions after them.
+//! let input = InputNode::new(xavier_normal(sequence_length, dim));
+//! let mask = InputNode::new(attention::causal_mask(sequence_length));
+//!
+//! let mut output = attention.forward(&input, &input, &input, Some(&mask));
+//!
+//! // Run as usual.
+//! output.forward();
+//! output.backward(1.0);
+//! output.zero_gradient();
+//! # }
+//! ```
+use std::sync::Arc;
+
+use ndarray;
+use rand;
+
+use nodes;
+use nodes::{HogwildParameter, InputNode, Node, ParameterNode};
+
+use nn::uniform;
+
+use {Arr, BoxedNode, Variable};
+
+/// Return an additive attention mask that stops each of `length`
+/// positions from attending to the positions after it.
+pub fn causal_mask(length: usize) -> Arr {
+    Arr::from_shape_fn((length, length), |(row, col)| {
+        if col > row {
+            ::std::f32::NEG_INFINITY
+        } else {
+            0.0
+        }
+    })
+}
+
+/// Scaled dot-product attention: `softmax(query * key^T / sqrt(dim) + mask) * value`.
+///
+/// The optional additive `mask` has one row per query and one column per key;
+/// use zeros to allow attending to a key, and negative infinity to prevent it.
+/// Every query must be allowed to attend to at least one key.
+pub fn scaled_dot_product<Q, K, V>(
+    query: &Variable<Q>,
+    key: &Variable<K>,
+    value: &Variable<V>,
+    mask: Option<&Variable<InputNode>>,
+) -> Variable<BoxedNode>
+where
+    Q: Node<Value = Arr, InputGradient = Arr>,
+    K: Node<Value = Arr, InputGradient = Arr>,
+    V: Node<Value = Arr, InputGradient = Arr>,
+{
+    let dim = query.value().cols();
+    assert_eq!(
+        key.value().cols(),
+        dim,
+        "Queries and keys must have the same dimension."
+    );
+
+    let scores = query.dot(&key.t()) * (1.0 / (dim as f32).sqrt());
+    let scores = match mask {
+        Some(mask) => (scores + mask.clone()).boxed(),
+        None => scores.boxed(),
+    };
+
+    scores.softmax().dot(value).boxed()
+}
+
+/// Holds shared parameters for a multi-head attention layer.
+///
+/// Construct this first, then use the `build` method to instantiate
+/// attention layers.
+#[derive(Debug, Serialize, Deserialize)]
+pub struct Parameters {
+    dim: usize,
+    num_heads: usize,
+
+    query_weights: Arc<nodes::HogwildParameter>,
+    key_weights: Arc<nodes::HogwildParameter>,
+    value_weights: Arc<nodes::HogwildParameter>,
+    output_weights: Arc<nodes::HogwildParameter>,
+}
+
+impl Clone for Parameters {
+    /// Clones the parameter values.
+    ///
+    /// (This is in contrast to creating a shared reference to
+    /// the same parameter object.)
+    fn clone(&self) -> Self {
+        Parameters {
+            dim: self.dim,
+            num_heads: self.num_heads,
+
+            query_weights: Arc::new(self.query_weights.as_ref().clone()),
+            key_weights: Arc::new(self.key_weights.as_ref().clone()),
+            value_weights: Arc::new(self.value_weights.as_ref().clone()),
+            output_weights: Arc::new(self.output_weights.as_ref().clone()),
+        }
+    }
+}
+
+impl Parameters {
+    /// Create new multi-head attention parameters for inputs with `dim`
+    /// columns, split across `num_heads` heads of `dim / num_heads`
+    /// columns each.
+    pub fn new<R: rand::Rng>(dim: usize, num_heads: usize, rng: &mut R) -> Self {
+        assert!(num_heads > 0, "Must have at least one head.");
+        assert_eq!(
+            dim % num_heads,
+            0,
+            "Dimension must be divisible by the number of heads."
+        );
+
+        let max = 1.0 / (dim as f32).sqrt();
+        let min = -max;
+
+        Parameters {
+            dim: dim,
+            num_heads: num_heads,
+
+            query_weights: Arc::new(HogwildParameter::new(uniform(dim, dim, min, max, rng))),
+            key_weights: Arc::new(HogwildParameter::new(uniform(dim, dim, min, max, rng))),
+            value_weights: Arc::new(HogwildParameter::new(uniform(dim, dim, min, max, rng))),
+            output_weights: Arc::new(HogwildParameter::new(uniform(dim, dim, min, max, rng))),
+        }
+    }
+
+    /// Build a multi-head attention layer.
+    pub fn build(&self) -> Layer {
+        Layer {
+            dim: self.dim,
+            num_heads: self.num_heads,
+
+            query_weights: ParameterNode::shared(self.query_weights.clone()),
+            key_weights: ParameterNode::shared(self.key_weights.clone()),
+            value_weights: ParameterNode::shared(self.value_weights.clone()),
+            output_weights: ParameterNode::shared(self.output_weights.clone()),
+        }
+    }
+}
+
+/// A multi-head attention layer.
+#[derive(Debug)]
+pub struct Layer {
+    dim: usize,
+    num_heads: usize,
+
+    query_weights: Variable<ParameterNode>,
+    key_weights: Variable<ParameterNode>,
+    value_weights: Variable<ParameterNode>,
+    output_weights: Variable<ParameterNode>,
+}
+
+impl Layer {
+    /// Project the queries, keys, and values for each head, attend
+    /// with scaled dot-product attention, and project the concatenated
+    /// results back to the input dimension. Pass the same variable
+    /// three times for self-attention.
+    pub fn forward<Q, K, V>(
+        &self,
+        query: &Variable<Q>,
+        key: &Variable<K>,
+        value: &Variable<V>,
+        mask: Option<&Variable<InputNode>>,
+    ) -> Variable<BoxedNode>
+    where
+        Q: Node<Value = Arr, InputGradient = Arr>,
+        K: Node<Value = Arr, InputGradient = Arr>,
+        V: Node<Value = Arr, InputGradient = Arr>,
+    {
+        let head_dims = vec![self.dim / self.num_heads; self.num_heads];
+        let axis = ndarray::Axis(1);
+
+        let queries = query.dot(&self.query_weights).split(&head_dims, axis);
+        let keys = key.dot(&self.key_weights).split(&head_dims, axis);
+        let values = value.dot(&self.value_weights).split(&head_dims, axis);
+
+        let heads: Vec<_> = izip!(&queries, &keys, &values)
+            .map(|(query, key, value)| scaled_dot_product(query, key, value, mask))
+            .collect();
+
+        Variable::concat(&heads, axis)
+            .dot(&self.output_weights)
+            .boxed()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+
+    use std::ops::Deref;
+
+    use super::*;
+    use assert_close;
+    use finite_difference;
+    use nn::xavier_normal;
+    use DataInput;
+
+    const TOLERANCE: f32 = 0.05;
+
+    #[test]
+    fn scaled_dot_product_finite_difference() {
+        let mut query = ParameterNode::new(xavier_normal(4, 3));
+        let mut key = ParameterNode::new(xavier_normal(5, 3));
+        let mut value = ParameterNode::new(xavier_normal(5, 2));
+        let mask = InputNode::new(Arr::from_shape_fn((4, 5), |(row, col)| {
+            if col > row + 1 {
+                ::std::f32::NEG_INFINITY
+            } else {
+                0.0
+            }
+        }));
+
+        let mut output = scaled_dot_product(&query, &key, &value, Some(&mask)).sigmoid();
+
+        assert_eq!(output.value().dim(), (4, 2));
+
+        for x in &mut [&mut query, &mut key, &mut value] {
+            let (difference, gradient) = finite_difference(x, &mut output);
+            assert_close(&difference, &gradient, TOLERANCE);
+            assert!(gradient.iter().all(|x| x.is_finite()));
+        }
+
+        // The first query only sees the first two values.
+        let attended = scaled_dot_product(&query, &key, &value, Some(&mask));
+        let weights = {
+            let scores = query.value().row(0).dot(&key.value().t()) / 3.0f32.sqrt();
+            let exp: Vec<f32> = scores.iter().take(2).map(|x| x.exp()).collect();
+            let sum: f32 = exp.iter().sum();
+            exp.into_iter().map(|x| x / sum).collect::<Vec<_>>()
+        };
+        let expected = &value.value().row(0) * weights[0] + &value.value().row(1) * weights[1];
+
+        assert_close(
+            &attended.value().slice(s![0..1, ..]).to_owned(),
+            &expected.insert_axis(ndarray::Axis(0)),
+            1e-4,
+        );
+    }
+
+    #[test]
+    fn multi_head_attention_finite_difference() {
+        let mut x = ParameterNode::new(xavier_normal(5, 6));
+        let mask = InputNode::new(causal_mask(5));
+
+        let layer = Parameters::new(6, 3, &mut rand::thread_rng()).build();
+        let mut output = layer.forward(&x, &x, &x, Some(&mask)).sigmoid();
+
+        assert_eq!(output.value().dim(), (5, 6));
+
+        let (difference, gradient) = finite_difference(&mut x, &mut output);
+        assert_close(&difference, &gradient, TOLERANCE);
+
+        let mut params = output.parameters().to_owned();
+        assert_eq!(params.len(), 5);
+
+        for x in params.iter_mut() {
+            let (difference, gradient) = finite_difference(x, &mut output);
+            assert_close(&difference, &gradient, TOLERANCE);
+        }
+
+        // Causal masking: the first output only depends on the first input.
+        let value = x.value().deref().clone();
+        let first = output.value().row(0).to_owned();
+
+        let mut changed = value.clone();
+        changed.row_mut(4).fill(1.0);
+        x.set_value(&changed);
+        output.forward();
+
+        assert_close(
+            &output
+                .value()
+                .row(0)
+                .to_owned()
+                .insert_axis(ndarray::Axis(0)),
+            &first.insert_axis(ndarray::Axis(0)),
+            1e-6,
+        );
+    }
+}
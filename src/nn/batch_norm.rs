@@ -0,0 +1,263 @@
+//! Module for batch normalisation.
+//!
+//! Batch normalisation normalises each column of a minibatch to zero
+//! mean and unit variance, and then applies a learnable gain and bias.
+//! In training mode, batch statistics are used and accumulated into
+//! running statistics, which are used instead in evaluation mode:
+//!
+//! ```rust
+//! # extern crate wyrm;
+//! # use wyrm::{set_training, InputNode};
+//! # use wyrm::nn::batch_norm;
+//! # use wyrm::nn::xavier_normal;
+//! # fn main() {
+//! let dim = 10;
+//!
+//! // Initialize the parameters.
+//! let parameters = batch_norm::Parameters::new(dim);
+//! let batch_norm = parameters.build();
+//!
+//! let input = InputNode::new(xavier_normal(32, dim));
+//! let mut output = batch_norm.forward(&input);
+//!
+//! // Run as usual.
+//! output.forward();
+//! output.backward(1.0);
+//! output.zero_gradient();
+//!
+//! // Use the running statistics for inference.
+//! set_training(false);
+//! output.forward();
+//! # }
+//! ```
+use std::rc::Rc;
+use std::sync::Arc;
+
+use nodes;
+use nodes::{BatchNormNode, HogwildParameter, Node, ParameterNode};
+
+use {merge_parameters, Arr, Variable};
+
+/// Default weight of the current batch in the running statistics.
+const DEFAULT_MOMENTUM: f32 = 0.1;
+
+/// Holds shared parameters and running statistics for a batch
+/// normalisation layer.
+///
+/// Construct this first, then use the `build` method to instantiate
+/// layers.
+#[derive(Debug, Serialize, Deserialize)]
+pub struct Parameters {
+    dim: usize,
+    momentum: f32,
+    gain: Arc<nodes::HogwildParameter>,
+    bias: Arc<nodes::HogwildParameter>,
+    running_mean: Arc<nodes::HogwildParameter>,
+    running_variance: Arc<nodes::HogwildParameter>,
+}
+
+impl Clone for Parameters {
+    /// Clones the parameter values.
+    ///
+    /// (This is in contrast to creating a shared reference to
+    /// the same parameter object.)
+    fn clone(&self) -> Self {
+        Parameters {
+            dim: self.dim,
+            momentum: self.momentum,
+            gain: Arc::new(self.gain.as_ref().clone()),
+            bias: Arc::new(self.bias.as_ref().clone()),
+            running_mean: Arc::new(self.running_mean.as_ref().clone()),
+            running_variance: Arc::new(self.running_variance.as_ref().clone()),
+        }
+    }
+}
+
+impl Parameters {
+    /// Create new batch normalisation parameters for inputs with `dim`
+    /// columns. The gain is initialized to ones and the bias to zeros.
+    pub fn new(dim: usize) -> Self {
+        Parameters::with_momentum(dim, DEFAULT_MOMENTUM)
+    }
+
+    /// Create new batch normalisation parameters, where each training
+    /// batch contributes a `momentum` fraction of the running statistics.
+    pub fn with_momentum(dim: usize, momentum: f32) -> Self {
+        assert!(
+            momentum > 0.0 && momentum <= 1.0,
+            "Momentum must be in (0, 1]."
+        );
+
+        Parameters {
+            dim: dim,
+            momentum: momentum,
+            gain: Arc::new(HogwildParameter::new(Arr::ones((1, dim)))),
+            bias: Arc::new(HogwildParameter::new(Arr::zeros((1, dim)))),
+            running_mean: Arc::new(HogwildParameter::new(Arr::zeros((1, dim)))),
+            running_variance: Arc::new(HogwildParameter::new(Arr::ones((1, dim)))),
+        }
+    }
+
+    /// Get the running mean of each column.
+    pub fn running_mean(&self) -> &Arr {
+        self.running_mean.value()
+    }
+
+    /// Get the running variance of each column.
+    pub fn running_variance(&self) -> &Arr {
+        self.running_variance.value()
+    }
+
+    /// Build a batch normalisation layer.
+    pub fn build(&self) -> Layer {
+        Layer {
+            dim: self.dim,
+            momentum: self.momentum,
+            gain: ParameterNode::shared(self.gain.clone()),
+            bias: ParameterNode::shared(self.bias.clone()),
+            running_mean: self.running_mean.clone(),
+            running_variance: self.running_variance.clone(),
+        }
+    }
+}
+
+/// A batch normalisation layer.
+#[derive(Debug)]
+pub struct Layer {
+    dim: usize,
+    momentum: f32,
+    gain: Variable<ParameterNode>,
+    bias: Variable<ParameterNode>,
+    running_mean: Arc<nodes::HogwildParameter>,
+    running_variance: Arc<nodes::HogwildParameter>,
+}
+
+impl Layer {
+    /// Normalise the columns of `input`, a minibatch with one example per row.
+    pub fn forward<T>(&self, input: &Variable<T>) -> Variable<BatchNormNode<T>>
+    where
+        T: Node<Value = Arr, InputGradient = Arr>,
+    {
+        assert_eq!(
+            input.value().cols(),
+            self.dim,
+            "Input must have {} columns.",
+            self.dim
+        );
+
+        let node = BatchNormNode::new(
+            Rc::clone(&input.node),
+            Rc::clone(&self.gain.node),
+            Rc::clone(&self.bias.node),
+            self.running_mean.clone(),
+            self.running_variance.clone(),
+            self.momentum,
+        );
+
+        Variable::new(
+            Rc::new(node),
+            merge_parameters(
+                &input.parameters,
+                &merge_parameters(&self.gain.parameters, &self.bias.parameters),
+            ),
+        )
+    }
+}
+
+#[cfg(test)]
+mod tests {
+
+    use std::ops::Deref;
+
+    use super::*;
+    use assert_close;
+    use finite_difference;
+    use nn::xavier_normal;
+    use {set_training, InputNode};
+
+    const TOLERANCE: f32 = 0.05;
+
+    #[test]
+    fn batch_norm_finite_difference() {
+        let mut x = ParameterNode::new(xavier_normal(8, 4));
+
+        let layer = Parameters::new(4).build();
+        let mut output = (layer.forward(&x.tanh()) * x.clone()).sigmoid();
+
+        let (difference, gradient) = finite_difference(&mut x, &mut output);
+        assert_close(&difference, &gradient, TOLERANCE);
+
+        let mut params = output.parameters().to_owned();
+        assert_eq!(params.len(), 3);
+
+        for x in params.iter_mut() {
+            let (difference, gradient) = finite_difference(x, &mut output);
+            assert_close(&difference, &gradient, TOLERANCE);
+        }
+
+        set_training(false);
+
+        let (difference, gradient) = finite_difference(&mut x, &mut output);
+        assert_close(&difference, &gradient, TOLERANCE);
+
+        set_training(true);
+    }
+
+    #[test]
+    fn batch_norm_running_statistics() {
+        let parameters = Parameters::with_momentum(3, 0.5);
+        let layer = parameters.build();
+
+        let value = xavier_normal(16, 3) * 2.0 + 1.0;
+        let input = InputNode::new(value.clone());
+        let mut output = layer.forward(&input);
+
+        // Building the graph leaves the running statistics alone.
+        assert_eq!(parameters.running_mean(), &Arr::zeros((1, 3)));
+        assert_eq!(parameters.running_variance(), &Arr::ones((1, 3)));
+
+        // Training mode normalises with batch statistics.
+        let means = output.value().mean_axis(ndarray::Axis(0));
+        assert!(means.iter().all(|x| x.abs() < 1e-4));
+
+        for _ in 0..20 {
+            output.forward();
+            output.backward(1.0);
+        }
+
+        let mean = value
+            .mean_axis(ndarray::Axis(0))
+            .insert_axis(ndarray::Axis(0));
+        let variance = (&value - &mean)
+            .map(|x| x * x)
+            .sum_axis(ndarray::Axis(0))
+            .insert_axis(ndarray::Axis(0))
+            / 15.0;
+
+        assert_close(parameters.running_mean(), &mean, 1e-3);
+        assert_close(parameters.running_variance(), &variance, 1e-3);
+
+        // Evaluation mode uses the running statistics, which
+        // are carried over when copying the parameters.
+        let restored_parameters = parameters.clone();
+        let restored = restored_parameters.build();
+        let input = InputNode::new(value.clone());
+        let output = restored.forward(&input);
+
+        assert_eq!(
+            restored_parameters.running_mean(),
+            parameters.running_mean()
+        );
+        assert_eq!(
+            restored_parameters.running_variance(),
+            parameters.running_variance()
+        );
+
+        set_training(false);
+        output.forward();
+        set_training(true);
+
+        let expected = (&value - &mean) / &variance.map(|x| (x + 1e-5).sqrt());
+        assert_close(output.value().deref(), &expected, 1e-3);
+    }
+}
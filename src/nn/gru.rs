@@ -0,0 +1,467 @@
+//! Module for GRU layers.
+//!
+//! You can create a GRU layer by first initializing its parameters,
+//! then applying it to your inputs:
+//!
+//! ```rust
+//! # extern crate rand;
+//! # extern crate wyrm;
+//! # extern crate ndarray;
+//! # use std::sync::Arc;
+//! # use std::rc::Rc;
+//! #
+//! # use wyrm::{HogwildParameter, InputNode, Node, ParameterNode};
+//! #
+//! # use wyrm::nn::xavier_normal;
+//! # use wyrm::nn::gru;
+//! #
+//! # use wyrm::{Arr, Variable};
+//! # fn main() {
+//! let input_dim = 10;
+//! let hidden_dim = 5;
+//!
+//! // Initialize the parameters.
+//! let parameters = gru::Parameters::new(input_dim, hidden_dim, &mut rand::thread_rng());
+//! let gru = parameters.build();
+//!
+//! // Construct the input nodes.
+//! let input: Vec<_> = (0..200)
+//!                      .map(|_| InputNode::new(xavier_normal(1, input_dim))).collect();
+//!
+//! // Construct a GRU with 200 steps of recursion.
+//! let mut hidden = gru.forward(&input);
+//!
+//! let mut last_hidden = hidden.last_mut().unwrap();
+//!
+//! // Run as usual.
+//! last_hidden.forward();
+//! last_hidden.backward(1.0);
+//! last_hidden.zero_gradient();
+//!
+//! // Reset the hidden state between sequences
+//! gru.reset_state();
+//! # }
+//! ```
+use std::rc::Rc;
+use std::sync::Arc;
+
+use ndarray;
+use rand;
+
+use nodes;
+use nodes::{HogwildParameter, Node, ParameterNode};
+
+use nn::uniform;
+
+use {Arr, DataInput, Variable};
+
+/// Holds shared parameters for a GRU cell.
+///
+/// Construct this first, then use the `build` method to instantiate
+/// GRU cell nodes.
+#[derive(Debug, Serialize, Deserialize)]
+pub struct Parameters {
+    input_dim: usize,
+    hidden_dim: usize,
+
+    update_gate_weights: Arc<nodes::HogwildParameter>,
+    update_gate_biases: Arc<nodes::HogwildParameter>,
+
+    reset_gate_weights: Arc<nodes::HogwildParameter>,
+    reset_gate_biases: Arc<nodes::HogwildParameter>,
+
+    candidate_weights: Arc<nodes::HogwildParameter>,
+    candidate_biases: Arc<nodes::HogwildParameter>,
+}
+
+impl Clone for Parameters {
+    /// Clones the parameter values.
+    ///
+    /// (This is in contrast to creating a shared reference to
+    /// the same parameter object.)
+    fn clone(&self) -> Self {
+        Parameters {
+            input_dim: self.input_dim,
+            hidden_dim: self.hidden_dim,
+
+            update_gate_weights: Arc::new(self.update_gate_weights.as_ref().clone()),
+            update_gate_biases: Arc::new(self.update_gate_biases.as_ref().clone()),
+
+            reset_gate_weights: Arc::new(self.reset_gate_weights.as_ref().clone()),
+            reset_gate_biases: Arc::new(self.reset_gate_biases.as_ref().clone()),
+
+            candidate_weights: Arc::new(self.candidate_weights.as_ref().clone()),
+            candidate_biases: Arc::new(self.candidate_biases.as_ref().clone()),
+        }
+    }
+}
+
+impl Parameters {
+    /// Create a new GRU parameters object.
+    pub fn new<R: rand::Rng>(input_dim: usize, hidden_dim: usize, rng: &mut R) -> Self {
+        let max = 1.0 / (hidden_dim as f32).sqrt();
+        let min = -max;
+
+        Self {
+            input_dim: input_dim,
+            hidden_dim: hidden_dim,
+
+            update_gate_weights: Arc::new(HogwildParameter::new(uniform(
+                input_dim + hidden_dim,
+                hidden_dim,
+                min,
+                max,
+                rng,
+            ))),
+            update_gate_biases: Arc::new(HogwildParameter::new(uniform(
+                1, hidden_dim, min, max, rng,
+            ))),
+
+            reset_gate_weights: Arc::new(HogwildParameter::new(uniform(
+                input_dim + hidden_dim,
+                hidden_dim,
+                min,
+                max,
+                rng,
+            ))),
+            reset_gate_biases: Arc::new(HogwildParameter::new(uniform(
+                1, hidden_dim, min, max, rng,
+            ))),
+
+            candidate_weights: Arc::new(HogwildParameter::new(uniform(
+                input_dim + hidden_dim,
+                hidden_dim,
+                min,
+                max,
+                rng,
+            ))),
+            candidate_biases: Arc::new(HogwildParameter::new(uniform(
+                1, hidden_dim, min, max, rng,
+            ))),
+        }
+    }
+
+    /// Build a GRU layer.
+    pub fn build(&self) -> Layer {
+        Layer::new(self.build_cell())
+    }
+
+    /// Build a GRU cell.
+    pub fn build_cell(&self) -> Cell {
+        Cell {
+            hidden_dim: self.hidden_dim,
+
+            update_gate_weights: ParameterNode::shared(self.update_gate_weights.clone()),
+            update_gate_biases: ParameterNode::shared(self.update_gate_biases.clone()),
+
+            reset_gate_weights: ParameterNode::shared(self.reset_gate_weights.clone()),
+            reset_gate_biases: ParameterNode::shared(self.reset_gate_biases.clone()),
+
+            candidate_weights: ParameterNode::shared(self.candidate_weights.clone()),
+            candidate_biases: ParameterNode::shared(self.candidate_biases.clone()),
+        }
+    }
+}
+
+/// A GRU cell.
+#[derive(Debug)]
+pub struct Cell {
+    hidden_dim: usize,
+
+    update_gate_weights: Variable<ParameterNode>,
+    update_gate_biases: Variable<ParameterNode>,
+
+    reset_gate_weights: Variable<ParameterNode>,
+    reset_gate_biases: Variable<ParameterNode>,
+
+    candidate_weights: Variable<ParameterNode>,
+    candidate_biases: Variable<ParameterNode>,
+}
+
+impl Cell {
+    /// Run a single GRU iteration over inputs.
+    ///
+    /// If this is the first cell, initialize the hidden state;
+    /// otherwise pass the hidden state from previous iterations.
+    pub fn forward<H, I>(
+        &self,
+        hidden: Variable<H>,
+        input: Variable<I>,
+    ) -> Variable<Rc<Node<Value = Arr, InputGradient = Arr>>>
+    where
+        H: Node<Value = Arr, InputGradient = Arr>,
+        I: Node<Value = Arr, InputGradient = Arr>,
+    {
+        let stacked_input = hidden.stack(&input, ndarray::Axis(1));
+
+        // Decide how much of the hidden state to keep
+        let update_gate = (stacked_input.dot(&self.update_gate_weights)
+            + self.update_gate_biases.clone())
+        .sigmoid();
+
+        // Decide how much of the hidden state the candidate sees
+        let reset_gate = (stacked_input.dot(&self.reset_gate_weights)
+            + self.reset_gate_biases.clone())
+        .sigmoid();
+
+        // Propose a new hidden state
+        let reset_hidden = reset_gate * hidden.clone();
+        let candidate = (reset_hidden
+            .stack(&input, ndarray::Axis(1))
+            .dot(&self.candidate_weights)
+            + self.candidate_biases.clone())
+        .tanh();
+
+        // Interpolate between the old and proposed hidden states
+        let hidden = (1.0 - update_gate.clone()) * candidate + update_gate * hidden;
+
+        hidden.boxed()
+    }
+}
+
+/// A GRU layer.
+#[derive(Debug)]
+pub struct Layer {
+    cell: Cell,
+    hidden: Variable<nodes::InputNode>,
+}
+
+impl Layer {
+    fn new(cell: Cell) -> Self {
+        let hidden_dim = cell.hidden_dim;
+
+        Layer {
+            cell: cell,
+            hidden: nodes::InputNode::new(Arr::zeros((1, hidden_dim))),
+        }
+    }
+    /// Construct a GRU layer over given inputs, returning the emitted
+    /// hidden states.
+    ///
+    /// The state of the layer is initialized with zero vectors. Use
+    /// `Cell` for custom initialization.
+    pub fn forward<T>(
+        &self,
+        inputs: &[Variable<T>],
+    ) -> Vec<Variable<Rc<Node<Value = Arr, InputGradient = Arr>>>>
+    where
+        T: Node<Value = Arr, InputGradient = Arr>,
+    {
+        let mut hidden = self.hidden.clone().boxed();
+
+        let outputs: Vec<_> = inputs
+            .iter()
+            .map(|input| {
+                hidden = self.cell.forward(hidden.clone(), input.clone());
+                hidden.clone()
+            })
+            .collect();
+
+        outputs
+    }
+    /// Reset the internal state of the layer.
+    pub fn reset_state(&self) {
+        self.hidden.set_value(0.0);
+    }
+}
+
+#[cfg(test)]
+mod tests {
+
+    use std::ops::Deref;
+
+    use super::*;
+    use assert_close;
+    use finite_difference;
+    use nn::losses::sparse_categorical_crossentropy;
+    use nn::xavier_normal;
+    use nodes::InputNode;
+    use optim::{Adam, Optimizer};
+    use DataInput;
+
+    const TOLERANCE: f32 = 0.2;
+
+    fn pi_digits(num: usize) -> Vec<usize> {
+        let pi_str = include_str!("pi.txt");
+        pi_str
+            .chars()
+            .filter_map(|x| x.to_digit(10))
+            .map(|x| x as usize)
+            .take(num)
+            .collect()
+    }
+
+    #[test]
+    fn gru_finite_difference() {
+        let num_steps = 10;
+        let dim = 10;
+
+        let mut xs: Vec<_> = (0..num_steps)
+            .map(|_| ParameterNode::new(xavier_normal(1, dim)))
+            .collect();
+
+        let gru_params = Parameters::new(dim, dim, &mut rand::thread_rng());
+        let gru = gru_params.build();
+
+        let mut hidden_states = gru.forward(&xs);
+        let hidden = hidden_states.last_mut().unwrap();
+
+        for x in &mut xs {
+            let (difference, gradient) = finite_difference(x, hidden);
+            assert_close(&difference, &gradient, TOLERANCE);
+        }
+
+        let mut params = hidden.parameters().to_owned();
+        assert_eq!(params.len(), num_steps + 6);
+
+        for x in params.iter_mut() {
+            let (difference, gradient) = finite_difference(x, hidden);
+            assert_close(&difference, &gradient, TOLERANCE);
+        }
+    }
+
+    #[test]
+    fn test_basic_gru() {
+        let input_dim = 10;
+        let hidden_dim = 5;
+
+        // Initialize the parameters.
+        let gru_params = Parameters::new(input_dim, hidden_dim, &mut rand::thread_rng());
+        let gru = gru_params.build_cell();
+
+        // Initialize the hidden state.
+        let hidden = InputNode::new(Arr::zeros((1, hidden_dim)));
+
+        // Construct the input node.
+        let input = InputNode::new(xavier_normal(1, input_dim));
+
+        // The forward method outputs the new hidden state.
+        let mut hidden = gru.forward(hidden, input.clone());
+
+        // Construct a deep RNN.
+        for _ in 0..200 {
+            hidden = gru.forward(hidden.clone(), input.clone());
+        }
+
+        // Run as usual.
+        hidden.forward();
+        hidden.backward(1.0);
+        hidden.zero_gradient();
+    }
+
+    #[test]
+    fn minibatch_gru_finite_difference() {
+        let batch_size = 4;
+        let num_steps = 5;
+        let dim = 6;
+
+        let gru_params = Parameters::new(dim, dim, &mut rand::thread_rng());
+        let gru = gru_params.build_cell();
+
+        let mut x = ParameterNode::new(xavier_normal(batch_size, dim));
+
+        let mut hidden = InputNode::new(Arr::zeros((batch_size, dim))).boxed();
+
+        for _ in 0..num_steps {
+            hidden = gru.forward(hidden.clone(), x.clone());
+        }
+
+        assert_eq!(hidden.value().shape(), &[batch_size, dim]);
+
+        let (difference, gradient) = finite_difference(&mut x, &mut hidden);
+        assert_close(&difference, &gradient, TOLERANCE);
+
+        let mut params = hidden.parameters().to_owned();
+
+        for param in params.iter_mut() {
+            let (difference, gradient) = finite_difference(param, &mut hidden);
+            assert_close(&difference, &gradient, TOLERANCE);
+        }
+    }
+
+    fn predicted_label(softmax_output: &Arr) -> usize {
+        softmax_output
+            .iter()
+            .enumerate()
+            .max_by(|&(_, x), &(_, y)| x.partial_cmp(y).unwrap())
+            .unwrap()
+            .0
+    }
+
+    #[test]
+    fn test_pi_digits() {
+        let num_epochs = 50;
+
+        let sequence_length = 4;
+        let num_digits = 10;
+        let input_dim = 16;
+        let hidden_dim = 32;
+
+        let gru_params = Parameters::new(input_dim, hidden_dim, &mut rand::thread_rng());
+        let gru = gru_params.build();
+
+        let final_layer = ParameterNode::new(xavier_normal(hidden_dim, num_digits));
+        let embeddings = ParameterNode::new(xavier_normal(num_digits, input_dim));
+        let y = nodes::IndexInputNode::new(&[0]);
+
+        let inputs: Vec<_> = (0..sequence_length)
+            .map(|_| nodes::IndexInputNode::new(&[0]))
+            .collect();
+        let embeddings: Vec<_> = inputs.iter().map(|input| embeddings.index(input)).collect();
+
+        let hidden_states = gru.forward(&embeddings);
+        let hidden = hidden_states.last().unwrap();
+
+        let prediction = hidden.dot(&final_layer);
+        let mut loss = sparse_categorical_crossentropy(&prediction, &y);
+        let optimizer = Adam::new().learning_rate(0.01);
+
+        let digits = pi_digits(100);
+
+        let mut correct = 0;
+        let mut total = 0;
+
+        for _ in 0..num_epochs {
+            let mut loss_val = 0.0;
+
+            correct = 0;
+            total = 0;
+
+            for i in 0..(digits.len() - sequence_length - 1) {
+                let digit_chunk = &digits[i..(i + sequence_length + 1)];
+                if digit_chunk.len() < sequence_length + 1 {
+                    break;
+                }
+
+                for (&digit, input) in digit_chunk[..digit_chunk.len() - 1].iter().zip(&inputs) {
+                    input.set_value(digit);
+                }
+
+                let target_digit = *digit_chunk.last().unwrap();
+                y.set_value(target_digit);
+
+                loss.forward();
+                loss.backward(1.0);
+
+                loss_val += loss.value().scalar_sum();
+
+                optimizer.step(loss.parameters());
+                loss.zero_gradient();
+
+                if target_digit == predicted_label(prediction.value().deref()) {
+                    correct += 1;
+                }
+
+                total += 1;
+            }
+
+            println!(
+                "Loss {}, accuracy {}",
+                loss_val,
+                correct as f32 / total as f32
+            );
+        }
+
+        assert!((correct as f32 / total as f32) > 0.75);
+    }
+}
@@ -0,0 +1,135 @@
+//! Module for layer normalisation.
+//!
+//! Layer normalisation normalises each row of its input to zero mean
+//! and unit variance, and then applies a learnable gain and bias:
+//!
+//! ```rust
+//! # extern crate wyrm;
+//! # use wyrm::InputNode;
+//! # use wyrm::nn::layer_norm;
+//! # use wyrm::nn::xavier_normal;
+//! # fn main() {
+//! let dim = 10;
+//!
+//! // Initialize the parameters.
+//! let parameters = layer_norm::Parameters::new(dim);
+//! let layer_norm = parameters.build();
+//!
+//! let input = InputNode::new(xavier_normal(3, dim));
+//! let mut output = layer_norm.forward(&input);
+//!
+//! // Run as usual.
+//! output.forward();
+//! output.backward(1.0);
+//! output.zero_gradient();
+//! # }
+//! ```
+use std::sync::Arc;
+
+use nodes;
+use nodes::{HogwildParameter, LayerNormNode, Node, ParameterNode};
+
+use {Arr, Variable};
+
+/// Holds shared parameters for a layer normalisation layer.
+///
+/// Construct this first, then use the `build` method to instantiate
+/// layers.
+#[derive(Debug, Serialize, Deserialize)]
+pub struct Parameters {
+    dim: usize,
+    gain: Arc<nodes::HogwildParameter>,
+    bias: Arc<nodes::HogwildParameter>,
+}
+
+impl Clone for Parameters {
+    /// Clones the parameter values.
+    ///
+    /// (This is in contrast to creating a shared reference to
+    /// the same parameter object.)
+    fn clone(&self) -> Self {
+        Parameters {
+            dim: self.dim,
+            gain: Arc::new(self.gain.as_ref().clone()),
+            bias: Arc::new(self.bias.as_ref().clone()),
+        }
+    }
+}
+
+impl Parameters {
+    /// Create new layer normalisation parameters for inputs with `dim`
+    /// columns. The gain is initialized to ones and the bias to zeros.
+    pub fn new(dim: usize) -> Self {
+        Parameters {
+            dim: dim,
+            gain: Arc::new(HogwildParameter::new(Arr::ones((1, dim)))),
+            bias: Arc::new(HogwildParameter::new(Arr::zeros((1, dim)))),
+        }
+    }
+
+    /// Build a layer normalisation layer.
+    pub fn build(&self) -> Layer {
+        Layer {
+            dim: self.dim,
+            gain: ParameterNode::shared(self.gain.clone()),
+            bias: ParameterNode::shared(self.bias.clone()),
+        }
+    }
+}
+
+/// A layer normalisation layer.
+#[derive(Debug)]
+pub struct Layer {
+    dim: usize,
+    gain: Variable<ParameterNode>,
+    bias: Variable<ParameterNode>,
+}
+
+impl Layer {
+    /// Normalise the rows of `input`.
+    pub fn forward<T>(&self, input: &Variable<T>) -> Variable<LayerNormNode<T>>
+    where
+        T: Node<Value = Arr, InputGradient = Arr>,
+    {
+        assert_eq!(
+            input.value().cols(),
+            self.dim,
+            "Input must have {} columns.",
+            self.dim
+        );
+
+        input.layer_norm(&self.gain, &self.bias)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+
+    use super::*;
+    use assert_close;
+    use finite_difference;
+    use nn::xavier_normal;
+
+    const TOLERANCE: f32 = 0.05;
+
+    #[test]
+    fn layer_norm_finite_difference() {
+        // Spread out each row, so that none has a near-zero variance.
+        let spread = Arr::from_shape_fn((4, 6), |(_, col)| col as f32 * 0.2 - 0.5);
+        let mut x = ParameterNode::new(spread + xavier_normal(4, 6) * 0.1);
+
+        let layer = Parameters::new(6).build();
+        let mut output = (layer.forward(&x.tanh()) * 2.0).sigmoid();
+
+        let (difference, gradient) = finite_difference(&mut x, &mut output);
+        assert_close(&difference, &gradient, TOLERANCE);
+
+        let mut params = output.parameters().to_owned();
+        assert_eq!(params.len(), 3);
+
+        for x in params.iter_mut() {
+            let (difference, gradient) = finite_difference(x, &mut output);
+            assert_close(&difference, &gradient, TOLERANCE);
+        }
+    }
+}
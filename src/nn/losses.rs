@@ -5,6 +5,7 @@ use std::rc::Rc;
 
 use nodes::{BackwardAction, Bor, ForwardAction, IndexInputNode, LogSoftmaxNode, PassCounter};
 use numerics;
+use numerics::{ArraySlice, ArraySliceMut};
 use {Arr, Node, Variable};
 
 /// Sparse categorical cross entropy loss.
@@ -12,6 +13,11 @@ use {Arr, Node, Variable};
 /// Note that this performs a log-softmax operation
 /// internally, so there is no need to perform a softmax
 /// manually.
+///
+/// With a single row of logits, every index in `y` is a target
+/// for that row. With a `(batch, classes)` matrix of logits, `y`
+/// must hold exactly one target per row. The losses of all rows
+/// are summed into a `(1, 1)` loss.
 pub fn sparse_categorical_crossentropy<T>(
     x: &Variable<T>,
     y: &Variable<IndexInputNode>,
@@ -24,6 +30,23 @@ where
     Variable::new(Rc::new(node), x.parameters.clone())
 }
 
+/// Sparse categorical cross entropy loss, returning a `(batch, 1)`
+/// column of per-row losses instead of their sum.
+///
+/// See `sparse_categorical_crossentropy` for the layout of `x` and `y`.
+pub fn sparse_categorical_crossentropy_per_row<T>(
+    x: &Variable<T>,
+    y: &Variable<IndexInputNode>,
+) -> Variable<SparseCategoricalCrossentropyNode<T>>
+where
+    T: Node<Value = Arr, InputGradient = Arr>,
+{
+    let node =
+        SparseCategoricalCrossentropyNode::new_per_row(Rc::clone(&x.node), Rc::clone(&y.node));
+
+    Variable::new(Rc::new(node), x.parameters.clone())
+}
+
 /// Sparse categorical cross-entropy loss node.
 #[derive(Debug)]
 pub struct SparseCategoricalCrossentropyNode<LHS> {
@@ -36,31 +59,62 @@ pub struct SparseCategoricalCrossentropyNode<LHS> {
     counter: PassCounter,
 }
 
+/// Return the row of the logits that the `idx`-th target refers to.
+fn target_row(num_rows: usize, idx: usize) -> usize {
+    if num_rows == 1 {
+        0
+    } else {
+        idx
+    }
+}
+
+fn check_targets(num_rows: usize, num_targets: usize) {
+    assert!(
+        num_rows == 1 || num_rows == num_targets,
+        "Minibatch cross-entropy needs one target per row: got {} rows and {} targets.",
+        num_rows,
+        num_targets
+    );
+}
+
+/// Compute the per-row (or summed) negative log-likelihood of the targets.
+fn negative_log_likelihood(loss_value: &mut Arr, log_softmax_value: &Arr, targets: &[usize]) {
+    let num_rows = log_softmax_value.rows();
+
+    check_targets(num_rows, targets.len());
+
+    loss_value.fill(0.0);
+
+    let loss_rows = loss_value.rows();
+
+    for (i, &idx) in targets.iter().enumerate() {
+        let row = target_row(num_rows, i);
+        loss_value[(target_row(loss_rows, row), 0)] += -log_softmax_value[(row, idx)];
+    }
+}
+
 impl<LHS> SparseCategoricalCrossentropyNode<LHS>
 where
     LHS: Node<Value = Arr, InputGradient = Arr>,
 {
     pub(crate) fn new(operand: Rc<LHS>, y: Rc<IndexInputNode>) -> Self {
-        assert!(
-            operand.value().rows() == 1,
-            "Minibatches not supported: rows must be 1."
-        );
-
-        let log_softmax = LogSoftmaxNode::new(Rc::clone(&operand));
-        let scalar_loss = {
-            let log_softmax_value = log_softmax.value();
-
-            let mut scalar_loss = 0.0;
+        SparseCategoricalCrossentropyNode::with_reduction(operand, y, false)
+    }
 
-            for &idx in y.value().iter() {
-                scalar_loss += -log_softmax_value[(0, idx)];
-            }
+    pub(crate) fn new_per_row(operand: Rc<LHS>, y: Rc<IndexInputNode>) -> Self {
+        SparseCategoricalCrossentropyNode::with_reduction(operand, y, true)
+    }
 
-            scalar_loss
-        };
+    fn with_reduction(operand: Rc<LHS>, y: Rc<IndexInputNode>, per_row: bool) -> Self {
+        let log_softmax = LogSoftmaxNode::new(Rc::clone(&operand));
 
-        let mut loss_value = Arr::zeros((1, 1));
-        loss_value.fill(scalar_loss);
+        let loss_rows = if per_row { operand.value().rows() } else { 1 };
+        let mut loss_value = Arr::zeros((loss_rows, 1));
+        negative_log_likelihood(
+            &mut loss_value,
+            log_softmax.value().deref(),
+            y.value().as_slice(),
+        );
 
         let gradient = operand.value().deref() * 0.0;
         let needs_gradient = operand.needs_gradient();
@@ -76,7 +130,8 @@ where
         }
     }
 
-    /// Return the predictions made by this layer.
+    /// Return the predictions made by this layer: the log-probabilities
+    /// of every class, one row per example.
     pub fn predictions(&self) -> Bor<Arr> {
         self.log_softmax.value()
     }
@@ -97,44 +152,48 @@ where
         self.log_softmax.forward();
         self.y.forward();
 
-        let softmax_value = self.log_softmax.value();
-        debug_assert!(
-            softmax_value.rows() == 1,
-            "Minibatches not supported: rows must be 1."
+        negative_log_likelihood(
+            &mut self.loss_value.borrow_mut(),
+            self.log_softmax.value().deref(),
+            self.y.value().as_slice(),
         );
-        let softmax_slice = softmax_value.deref().as_slice().unwrap();
-
-        let mut loss_value = 0.0;
-
-        for &idx in self.y.value().iter() {
-            loss_value += -softmax_slice[idx];
-        }
-
-        self.loss_value.borrow_mut().fill(loss_value);
     }
     /// The backpropagation mechanics for this node are a little strange,
     /// because it uses the log-softmax node for the forward pass but not
     /// for the backward pass.
-    fn backward(&self, _: &Ref<Self::InputGradient>) {
-        // TODO: actually use the input gradient
+    fn backward(&self, gradient: &Ref<Self::InputGradient>) {
         let beta = match self.counter.backward() {
             BackwardAction::Set => 0.0,
             BackwardAction::Increment => 1.0,
         };
 
         {
-            let mut gradient = self.gradient.borrow_mut();
-            let gradient_slice = gradient.as_slice_mut().unwrap();
-
+            let mut operand_gradient = self.gradient.borrow_mut();
             let value = self.log_softmax.value();
-            let value_slice = value.as_slice().unwrap();
 
-            for (grad, &val) in izip!(gradient_slice.iter_mut(), value_slice.iter()) {
-                *grad = beta * *grad + numerics::exp(val);
-            }
+            operand_gradient.map_inplace(|x| *x *= beta);
+
+            let num_rows = operand_gradient.rows();
+
+            // Each target contributes upstream * (softmax - one_hot(target))
+            // to the gradient of its row.
+            for (i, &idx) in self.y.value().iter().enumerate() {
+                let row = target_row(num_rows, i);
+                let upstream = gradient[(target_row(gradient.rows(), row), 0)];
+
+                {
+                    let mut grad_row = operand_gradient.row_mut(row);
+                    let value_row = value.row(row);
+
+                    for (grad, &val) in izip!(
+                        grad_row.fast_slice_mut().iter_mut(),
+                        value_row.fast_slice().iter()
+                    ) {
+                        *grad += upstream * numerics::exp(val);
+                    }
+                }
 
-            for &idx in self.y.value().iter() {
-                gradient_slice[idx] -= 1.0;
+                operand_gradient[(row, idx)] -= upstream;
             }
         }
 
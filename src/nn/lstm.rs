@@ -417,6 +417,41 @@ mod tests {
         hidden.zero_gradient();
     }
 
+    #[test]
+    fn minibatch_lstm_finite_difference() {
+        let batch_size = 4;
+        let num_steps = 5;
+        let dim = 6;
+
+        let lstm_params = Parameters::new(dim, dim, &mut rand::thread_rng());
+        let lstm = lstm_params.build_cell();
+
+        let mut x = ParameterNode::new(xavier_normal(batch_size, dim));
+
+        let mut state = (
+            InputNode::new(Arr::zeros((batch_size, dim))).boxed(),
+            InputNode::new(Arr::zeros((batch_size, dim))).boxed(),
+        );
+
+        for _ in 0..num_steps {
+            state = lstm.forward(state.clone(), x.clone());
+        }
+
+        let (_, mut hidden) = state;
+
+        assert_eq!(hidden.value().shape(), &[batch_size, dim]);
+
+        let (difference, gradient) = finite_difference(&mut x, &mut hidden);
+        assert_close(&difference, &gradient, TOLERANCE);
+
+        let mut params = hidden.parameters().to_owned();
+
+        for param in params.iter_mut() {
+            let (difference, gradient) = finite_difference(param, &mut hidden);
+            assert_close(&difference, &gradient, TOLERANCE);
+        }
+    }
+
     fn predicted_label(softmax_output: &Arr) -> usize {
         softmax_output
             .iter()
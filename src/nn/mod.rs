@@ -1,7 +1,12 @@
 //! Neural network components.
 
+pub mod attention;
+pub mod batch_norm;
+pub mod gru;
+pub mod layer_norm;
 pub mod losses;
 pub mod lstm;
+pub mod transformer;
 
 use rand;
 use rand::distributions::{Distribution, Normal, Uniform};
@@ -0,0 +1,414 @@
+//! Module for transformer encoder layers.
+//!
+//! A transformer encoder is a drop-in replacement for an LSTM layer:
+//! initialize its parameters, build it, and apply it to a sequence
+//! of inputs:
+//!
+//! ```rust
+//! # extern crate rand;
+//! # extern crate wyrm;
+//! # use wyrm::InputNode;
+//! # use wyrm::nn::transformer;
+//! # use wyrm::nn::xavier_normal;
+//! # fn main() {
+//! let dim = 8;
+//! let num_heads = 2;
+//! let num_layers = 2;
+//!
+//! // Initialize the parameters.
+//! let parameters = transformer::Parameters::new(
+//!     dim,
+//!     num_heads,
+//!     num_layers,
+//!     transformer::Positions::Sinusoidal,
+//!     &mut rand::thread_rng(),
+//! );
+//! let encoder = parameters.build();
+//!
+//! // Construct the input nodes.
+//! let input: Vec<_> = (0..10)
+//!                      .map(|_| InputNode::new(xavier_normal(1, dim))).collect();
+//!
+//! // Encode the sequence, giving one output per input.
+//! let mut hidden = encoder.forward(&input);
+//!
+//! let mut last_hidden = hidden.last_mut().unwrap();
+//!
+//! // Run as usual.
+//! last_hidden.forward();
+//! last_hidden.backward(1.0);
+//! last_hidden.zero_gradient();
+//! # }
+//! ```
+use std::sync::Arc;
+
+use ndarray;
+use rand;
+
+use nodes;
+use nodes::{HogwildParameter, IndexInputNode, InputNode, Node, ParameterNode};
+
+use nn::uniform;
+use nn::{attention, layer_norm};
+
+use {Arr, BoxedNode, Variable};
+
+/// How positional information is added to the inputs.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum Positions {
+    /// Fixed sinusoidal embeddings, for sequences of any length.
+    Sinusoidal,
+    /// Learned embeddings, for sequences of up to the given length.
+    Learned(usize),
+}
+
+/// Width of the feed-forward sublayers, as a multiple of the input dimension.
+const FEEDFORWARD_EXPANSION: usize = 4;
+
+/// Return sinusoidal positional embeddings for a sequence of `length` positions.
+fn sinusoidal_positions(length: usize, dim: usize) -> Arr {
+    Arr::from_shape_fn((length, dim), |(position, col)| {
+        let frequency = 1.0 / 10_000f32.powf((col - col % 2) as f32 / dim as f32);
+        let angle = position as f32 * frequency;
+
+        if col % 2 == 0 {
+            angle.sin()
+        } else {
+            angle.cos()
+        }
+    })
+}
+
+/// Holds shared parameters for a single encoder block.
+#[derive(Debug, Serialize, Deserialize)]
+struct BlockParameters {
+    attention: attention::Parameters,
+    attention_norm: layer_norm::Parameters,
+
+    hidden_weights: Arc<nodes::HogwildParameter>,
+    hidden_biases: Arc<nodes::HogwildParameter>,
+    output_weights: Arc<nodes::HogwildParameter>,
+    output_biases: Arc<nodes::HogwildParameter>,
+    feedforward_norm: layer_norm::Parameters,
+}
+
+impl Clone for BlockParameters {
+    fn clone(&self) -> Self {
+        BlockParameters {
+            attention: self.attention.clone(),
+            attention_norm: self.attention_norm.clone(),
+
+            hidden_weights: Arc::new(self.hidden_weights.as_ref().clone()),
+            hidden_biases: Arc::new(self.hidden_biases.as_ref().clone()),
+            output_weights: Arc::new(self.output_weights.as_ref().clone()),
+            output_biases: Arc::new(self.output_biases.as_ref().clone()),
+            feedforward_norm: self.feedforward_norm.clone(),
+        }
+    }
+}
+
+impl BlockParameters {
+    fn new<R: rand::Rng>(dim: usize, num_heads: usize, rng: &mut R) -> Self {
+        let hidden_dim = FEEDFORWARD_EXPANSION * dim;
+
+        let max = 1.0 / (dim as f32).sqrt();
+        let hidden_max = 1.0 / (hidden_dim as f32).sqrt();
+
+        BlockParameters {
+            attention: attention::Parameters::new(dim, num_heads, rng),
+            attention_norm: layer_norm::Parameters::new(dim),
+
+            hidden_weights: Arc::new(HogwildParameter::new(uniform(
+                dim, hidden_dim, -max, max, rng,
+            ))),
+            hidden_biases: Arc::new(HogwildParameter::new(Arr::zeros((1, hidden_dim)))),
+            output_weights: Arc::new(HogwildParameter::new(uniform(
+                hidden_dim,
+                dim,
+                -hidden_max,
+                hidden_max,
+                rng,
+            ))),
+            output_biases: Arc::new(HogwildParameter::new(Arr::zeros((1, dim)))),
+            feedforward_norm: layer_norm::Parameters::new(dim),
+        }
+    }
+
+    fn build(&self) -> Block {
+        Block {
+            attention: self.attention.build(),
+            attention_norm: self.attention_norm.build(),
+
+            hidden_weights: ParameterNode::shared(self.hidden_weights.clone()),
+            hidden_biases: ParameterNode::shared(self.hidden_biases.clone()),
+            output_weights: ParameterNode::shared(self.output_weights.clone()),
+            output_biases: ParameterNode::shared(self.output_biases.clone()),
+            feedforward_norm: self.feedforward_norm.build(),
+        }
+    }
+}
+
+/// Holds shared parameters for a transformer encoder.
+///
+/// Construct this first, then use the `build` method to instantiate
+/// encoder layers.
+#[derive(Debug, Serialize, Deserialize)]
+pub struct Parameters {
+    dim: usize,
+    position_embeddings: Option<Arc<nodes::HogwildParameter>>,
+    blocks: Vec<BlockParameters>,
+}
+
+impl Clone for Parameters {
+    /// Clones the parameter values.
+    ///
+    /// (This is in contrast to creating a shared reference to
+    /// the same parameter object.)
+    fn clone(&self) -> Self {
+        Parameters {
+            dim: self.dim,
+            position_embeddings: self
+                .position_embeddings
+                .as_ref()
+                .map(|x| Arc::new(x.as_ref().clone())),
+            blocks: self.blocks.clone(),
+        }
+    }
+}
+
+impl Parameters {
+    /// Create new transformer encoder parameters for inputs with `dim` columns,
+    /// with `num_layers` blocks of multi-head self-attention over `num_heads` heads,
+    /// each followed by a feed-forward sublayer. Both sublayers have residual
+    /// connections and are followed by layer normalisation.
+    pub fn new<R: rand::Rng>(
+        dim: usize,
+        num_heads: usize,
+        num_layers: usize,
+        positions: Positions,
+        rng: &mut R,
+    ) -> Self {
+        let position_embeddings = match positions {
+            Positions::Sinusoidal => None,
+            Positions::Learned(max_length) => Some(Arc::new(HogwildParameter::new(uniform(
+                max_length, dim, -0.1, 0.1, rng,
+            )))),
+        };
+
+        Parameters {
+            dim: dim,
+            position_embeddings: position_embeddings,
+            blocks: (0..num_layers)
+                .map(|_| BlockParameters::new(dim, num_heads, rng))
+                .collect(),
+        }
+    }
+
+    /// Build a transformer encoder layer.
+    pub fn build(&self) -> EncoderLayer {
+        EncoderLayer {
+            dim: self.dim,
+            position_embeddings: self
+                .position_embeddings
+                .as_ref()
+                .map(|x| ParameterNode::shared(x.clone())),
+            blocks: self.blocks.iter().map(|x| x.build()).collect(),
+        }
+    }
+}
+
+/// A single encoder block.
+#[derive(Debug)]
+struct Block {
+    attention: attention::Layer,
+    attention_norm: layer_norm::Layer,
+
+    hidden_weights: Variable<ParameterNode>,
+    hidden_biases: Variable<ParameterNode>,
+    output_weights: Variable<ParameterNode>,
+    output_biases: Variable<ParameterNode>,
+    feedforward_norm: layer_norm::Layer,
+}
+
+impl Block {
+    fn forward(
+        &self,
+        input: &Variable<BoxedNode>,
+        mask: Option<&Variable<InputNode>>,
+    ) -> Variable<BoxedNode> {
+        let attended = self.attention.forward(input, input, input, mask);
+        let input = self.attention_norm.forward(&(input.clone() + attended));
+
+        let hidden = (input.dot(&self.hidden_weights) + self.hidden_biases.clone()).relu();
+        let output = hidden.dot(&self.output_weights) + self.output_biases.clone();
+
+        self.feedforward_norm.forward(&(input + output)).boxed()
+    }
+}
+
+/// A transformer encoder layer.
+#[derive(Debug)]
+pub struct EncoderLayer {
+    dim: usize,
+    position_embeddings: Option<Variable<ParameterNode>>,
+    blocks: Vec<Block>,
+}
+
+impl EncoderLayer {
+    /// Construct a transformer encoder over given inputs (each of shape
+    /// `(1, dim)`), returning one encoded output per input.
+    pub fn forward<T>(&self, inputs: &[Variable<T>]) -> Vec<Variable<BoxedNode>>
+    where
+        T: Node<Value = Arr, InputGradient = Arr>,
+    {
+        self.encode(inputs, None)
+    }
+
+    /// Like `forward`, but with an additive attention mask of shape
+    /// `(inputs.len(), inputs.len())`, such as `attention::causal_mask`.
+    pub fn forward_masked<T>(
+        &self,
+        inputs: &[Variable<T>],
+        mask: &Variable<InputNode>,
+    ) -> Vec<Variable<BoxedNode>>
+    where
+        T: Node<Value = Arr, InputGradient = Arr>,
+    {
+        self.encode(inputs, Some(mask))
+    }
+
+    fn encode<T>(
+        &self,
+        inputs: &[Variable<T>],
+        mask: Option<&Variable<InputNode>>,
+    ) -> Vec<Variable<BoxedNode>>
+    where
+        T: Node<Value = Arr, InputGradient = Arr>,
+    {
+        let length = inputs.len();
+        let sequence = Variable::concat(inputs, ndarray::Axis(0));
+
+        assert_eq!(
+            sequence.value().dim(),
+            (length, self.dim),
+            "Inputs must have shape (1, {}).",
+            self.dim
+        );
+
+        let mut hidden = match self.position_embeddings {
+            Some(ref embeddings) => {
+                let max_length = embeddings.value().rows();
+                assert!(
+                    length <= max_length,
+                    "Sequence of length {} exceeds the maximum of {} learned positions.",
+                    length,
+                    max_length
+                );
+
+                let positions: Vec<_> = (0..length).collect();
+                (sequence + embeddings.index(&IndexInputNode::new(&positions))).boxed()
+            }
+            None => (sequence + InputNode::new(sinusoidal_positions(length, self.dim))).boxed(),
+        };
+
+        for block in &self.blocks {
+            hidden = block.forward(&hidden, mask);
+        }
+
+        hidden
+            .split(&vec![1; length], ndarray::Axis(0))
+            .into_iter()
+            .map(|x| x.boxed())
+            .collect()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+
+    use super::*;
+    use assert_close;
+    use finite_difference;
+    use nn::xavier_normal;
+    use DataInput;
+
+    const TOLERANCE: f32 = 0.05;
+
+    #[test]
+    fn transformer_finite_difference() {
+        let num_steps = 4;
+        let dim = 4;
+
+        for &positions in &[Positions::Sinusoidal, Positions::Learned(num_steps)] {
+            let mut xs: Vec<_> = (0..num_steps)
+                .map(|_| ParameterNode::new(xavier_normal(1, dim)))
+                .collect();
+
+            let parameters = Parameters::new(dim, 2, 2, positions, &mut rand::thread_rng());
+            let encoder = parameters.build();
+
+            let mut hidden_states = encoder.forward(&xs);
+            let mut hidden = hidden_states.last_mut().unwrap().sigmoid();
+
+            for x in &mut xs {
+                let (difference, gradient) = finite_difference(x, &mut hidden);
+                assert_close(&difference, &gradient, TOLERANCE);
+            }
+
+            let mut params = hidden.parameters().to_owned();
+            let num_params = match positions {
+                Positions::Sinusoidal => 2 * 12,
+                Positions::Learned(_) => 2 * 12 + 1,
+            };
+            assert_eq!(params.len(), num_steps + num_params);
+
+            for x in params.iter_mut() {
+                let (difference, gradient) = finite_difference(x, &mut hidden);
+                assert_close(&difference, &gradient, TOLERANCE);
+            }
+        }
+    }
+
+    #[test]
+    fn transformer_causal_mask() {
+        let num_steps = 5;
+        let dim = 4;
+
+        let xs: Vec<_> = (0..num_steps)
+            .map(|_| InputNode::new(xavier_normal(1, dim)))
+            .collect();
+        let mask = InputNode::new(attention::causal_mask(num_steps));
+
+        let parameters = Parameters::new(
+            dim,
+            2,
+            2,
+            Positions::Learned(num_steps),
+            &mut rand::thread_rng(),
+        );
+        let encoder = parameters.build();
+        let mut hidden_states = encoder.forward_masked(&xs, &mask);
+
+        hidden_states[0].forward();
+        hidden_states[0].backward(1.0);
+        let first = hidden_states[0].value().clone();
+
+        xs[num_steps - 1].set_value(1.0);
+        hidden_states[0].forward();
+
+        assert_close(&hidden_states[0].value(), &first, 1e-6);
+    }
+
+    #[test]
+    #[should_panic]
+    fn transformer_learned_positions_too_short() {
+        let dim = 4;
+
+        let xs: Vec<_> = (0..3)
+            .map(|_| InputNode::new(xavier_normal(1, dim)))
+            .collect();
+
+        let parameters = Parameters::new(dim, 2, 1, Positions::Learned(2), &mut rand::thread_rng());
+        parameters.build().forward(&xs);
+    }
+}
@@ -1,4 +1,5 @@
 use std;
+use std::any::Any;
 use std::cell::{Cell, Ref, RefCell};
 use std::fmt;
 use std::ops::{AddAssign, Deref, DerefMut};
@@ -12,6 +13,8 @@ use smallvec::SmallVec;
 
 use hibitset::BitSet;
 
+use rand::{Rng, SeedableRng, XorShiftRng};
+
 use numerics;
 use numerics::{ArraySlice, ArraySliceMut, ArraySliceOps};
 
@@ -153,10 +156,67 @@ impl Node for Rc<Node<Value = Arr, InputGradient = Arr>> {
     }
 }
 
+/// Compute the shape resulting from broadcasting `lhs` against `rhs`.
+///
+/// Follows numpy semantics: along every axis, the dimensions must
+/// either be equal or one of them must be 1.
+fn broadcast_shape(lhs: &[usize], rhs: &[usize]) -> (usize, usize) {
+    let broadcast_dim = |lhs_dim: usize, rhs_dim: usize| {
+        if lhs_dim == rhs_dim || rhs_dim == 1 {
+            lhs_dim
+        } else if lhs_dim == 1 {
+            rhs_dim
+        } else {
+            panic!(
+                "Operands with shapes {:?} and {:?} cannot be broadcast together.",
+                lhs, rhs
+            )
+        }
+    };
+
+    (broadcast_dim(lhs[0], rhs[0]), broadcast_dim(lhs[1], rhs[1]))
+}
+
+/// Apply an elementwise binary function to `lhs` and `rhs`, broadcasting
+/// both to the shape of `dest`.
+fn broadcast_binary_op<F>(dest: &mut Arr, lhs: &Arr, rhs: &Arr, func: F)
+where
+    F: Fn(f32, f32) -> f32,
+{
+    dest.assign(lhs);
+    dest.zip_mut_with(rhs, |x, &y| *x = func(*x, y));
+}
+
+/// Sum-reduce `gradient` (which has the shape of the broadcast result)
+/// into `dest` (which has the shape of the broadcast operand), scaling it
+/// by `alpha`.
+fn reduce_broadcast_gradient(dest: &mut Arr, gradient: &Arr, alpha: f32, action: &BackwardAction) {
+    let (dest_rows, dest_cols) = (dest.rows(), dest.cols());
+
+    if *action == BackwardAction::Set {
+        dest.fill(0.0);
+    }
+
+    for (row_idx, grad_row) in gradient.genrows().into_iter().enumerate() {
+        let dest_row_idx = if dest_rows == 1 { 0 } else { row_idx };
+        let mut dest_row = dest.subview_mut(Axis(0), dest_row_idx);
+        let dest_row = dest_row.fast_slice_mut();
+        let grad_row = grad_row.fast_slice();
+
+        if dest_cols == 1 {
+            dest_row[0] += alpha * numerics::simd_sum(grad_row);
+        } else {
+            numerics::simd_scaled_add(dest_row, grad_row, alpha);
+        }
+    }
+}
+
 #[derive(Debug)]
 pub struct AddNode<LHS, RHS> {
     value: RefCell<Arr>,
     gradient: RefCell<Arr>,
+    lhs_gradient: RefCell<Arr>,
+    rhs_gradient: RefCell<Arr>,
     lhs: Rc<LHS>,
     rhs: Rc<RHS>,
     needs_gradient: bool,
@@ -170,12 +230,25 @@ where
 {
     pub fn new(lhs: Rc<LHS>, rhs: Rc<RHS>) -> Self {
         let needs_gradient = lhs.needs_gradient() || rhs.needs_gradient();
-        let value = lhs.value().deref() + rhs.value().deref();
-        let gradient = rhs.value().deref() * 0.0;
+        let shape = broadcast_shape(lhs.value().shape(), rhs.value().shape());
+
+        let mut value = Arr::zeros(shape);
+        broadcast_binary_op(
+            &mut value,
+            lhs.value().deref(),
+            rhs.value().deref(),
+            |x, y| x + y,
+        );
+
+        let gradient = &value * 0.0;
+        let lhs_gradient = lhs.value().deref() * 0.0;
+        let rhs_gradient = rhs.value().deref() * 0.0;
 
         AddNode {
             value: RefCell::new(value),
             gradient: RefCell::new(gradient),
+            lhs_gradient: RefCell::new(lhs_gradient),
+            rhs_gradient: RefCell::new(rhs_gradient),
             lhs: lhs,
             rhs: rhs,
             needs_gradient: needs_gradient,
@@ -204,23 +277,32 @@ where
 
         debug_assert_eq!(
             lhs_value.shape(),
-            self.value().shape(),
+            self.lhs_gradient.borrow().shape(),
             "LHS operand changed shape."
         );
         debug_assert_eq!(
             rhs_value.shape(),
-            self.value().shape(),
+            self.rhs_gradient.borrow().shape(),
             "RHS operand changed shape."
         );
 
         let mut self_value = self.value.borrow_mut();
 
-        for (v, &lhs, &rhs) in izip!(
-            self_value.fast_slice_mut(),
-            lhs_value.fast_slice(),
-            rhs_value.fast_slice()
-        ) {
-            *v = lhs + rhs;
+        if lhs_value.shape() == rhs_value.shape() {
+            for (v, &lhs, &rhs) in izip!(
+                self_value.fast_slice_mut(),
+                lhs_value.fast_slice(),
+                rhs_value.fast_slice()
+            ) {
+                *v = lhs + rhs;
+            }
+        } else {
+            broadcast_binary_op(
+                self_value.deref_mut(),
+                lhs_value.deref(),
+                rhs_value.deref(),
+                |x, y| x + y,
+            );
         }
     }
     fn backward(&self, gradient: &Ref<Self::InputGradient>) {
@@ -237,8 +319,30 @@ where
 
         if self.counter.recurse_backward() {
             let gradient = self.gradient.borrow();
-            self.lhs.backward(&gradient);
-            self.rhs.backward(&gradient);
+
+            if self.lhs_gradient.borrow().shape() == gradient.shape() {
+                self.lhs.backward(&gradient);
+            } else {
+                reduce_broadcast_gradient(
+                    self.lhs_gradient.borrow_mut().deref_mut(),
+                    gradient.deref(),
+                    1.0,
+                    &BackwardAction::Set,
+                );
+                self.lhs.backward(&self.lhs_gradient.borrow());
+            }
+
+            if self.rhs_gradient.borrow().shape() == gradient.shape() {
+                self.rhs.backward(&gradient);
+            } else {
+                reduce_broadcast_gradient(
+                    self.rhs_gradient.borrow_mut().deref_mut(),
+                    gradient.deref(),
+                    1.0,
+                    &BackwardAction::Set,
+                );
+                self.rhs.backward(&self.rhs_gradient.borrow());
+            }
         }
     }
     fn value(&self) -> Bor<Self::Value> {
@@ -545,6 +649,290 @@ where
     }
 }
 
+/// Compute the `[start, end)` offsets of consecutive blocks of the given sizes.
+fn block_offsets<I: IntoIterator<Item = usize>>(sizes: I) -> Vec<(usize, usize)> {
+    sizes
+        .into_iter()
+        .scan(0, |start, size| {
+            let block = (*start, *start + size);
+            *start += size;
+            Some(block)
+        })
+        .collect()
+}
+
+fn check_axis(axis: ndarray::Axis) {
+    match axis {
+        ndarray::Axis(0) | ndarray::Axis(1) => {}
+        _ => panic!("Stacking tensors not allowed."),
+    }
+}
+
+/// Concatenates any number of operands along an axis in a single node.
+#[derive(Debug)]
+pub struct ConcatNode<OP> {
+    axis: ndarray::Axis,
+    offsets: Vec<(usize, usize)>,
+    value: RefCell<Arr>,
+    operand_gradients: Vec<RefCell<Arr>>,
+    operands: Vec<Rc<OP>>,
+    needs_gradient: bool,
+    counter: PassCounter,
+}
+
+impl<OP> ConcatNode<OP>
+where
+    OP: Node<Value = Arr>,
+{
+    pub fn new(operands: Vec<Rc<OP>>, axis: ndarray::Axis) -> Self {
+        assert!(!operands.is_empty(), "Cannot concatenate zero operands.");
+        check_axis(axis);
+
+        let needs_gradient = operands.iter().any(|x| x.needs_gradient());
+
+        let value = {
+            let values: Vec<_> = operands.iter().map(|x| x.value()).collect();
+            let views: Vec<_> = values.iter().map(|x| x.view()).collect();
+
+            ndarray::stack(axis, &views).expect("Unable to concatenate arrays.")
+        };
+
+        let offsets = block_offsets(operands.iter().map(|x| x.value().len_of(axis)));
+        let operand_gradients = operands
+            .iter()
+            .map(|x| RefCell::new(x.value().deref() * 0.0))
+            .collect();
+
+        ConcatNode {
+            axis: axis,
+            offsets: offsets,
+            value: RefCell::new(value),
+            operand_gradients: operand_gradients,
+            operands: operands,
+            needs_gradient: needs_gradient,
+            counter: PassCounter::default(),
+        }
+    }
+}
+
+impl<OP> Node for ConcatNode<OP>
+where
+    OP: Node<Value = Arr, InputGradient = Arr>,
+{
+    type Value = Arr;
+    type InputGradient = Arr;
+    fn forward(&self) {
+        if self.counter.forward() == ForwardAction::Cached {
+            return;
+        }
+
+        let mut self_value = self.value.borrow_mut();
+
+        for (operand, &(start, end)) in self.operands.iter().zip(self.offsets.iter()) {
+            operand.forward();
+
+            self_value
+                .slice_axis_mut(self.axis, ndarray::Slice::from(start..end))
+                .assign(operand.value().deref());
+        }
+    }
+    fn backward(&self, gradient: &Ref<Self::InputGradient>) {
+        let action = self.counter.backward();
+
+        for (operand_gradient, &(start, end)) in
+            self.operand_gradients.iter().zip(self.offsets.iter())
+        {
+            let mut operand_gradient = operand_gradient.borrow_mut();
+            let gradient_block = gradient.slice_axis(self.axis, ndarray::Slice::from(start..end));
+
+            match action {
+                BackwardAction::Set => operand_gradient.assign(&gradient_block),
+                BackwardAction::Increment => operand_gradient.add_assign(&gradient_block),
+            }
+        }
+
+        if self.counter.recurse_backward() {
+            for (operand, operand_gradient) in
+                self.operands.iter().zip(self.operand_gradients.iter())
+            {
+                operand.backward(&operand_gradient.borrow());
+            }
+        }
+    }
+
+    fn value(&self) -> Bor<Self::Value> {
+        Bor::RefGuard(self.value.borrow())
+    }
+    fn needs_gradient(&self) -> bool {
+        self.needs_gradient
+    }
+    fn clear(&self) {
+        if !self.counter.is_zero() {
+            for operand in &self.operands {
+                operand.clear();
+            }
+            self.counter.clear();
+        }
+    }
+}
+
+/// The operand of a split, shared by all of its `SplitNode` pieces.
+/// Collects the gradients of all the pieces into a single buffer
+/// before passing it on to the operand.
+#[derive(Debug)]
+pub struct SplitSource<OP> {
+    axis: ndarray::Axis,
+    operand_gradient: RefCell<Arr>,
+    operand: Rc<OP>,
+    counter: PassCounter,
+}
+
+impl<OP> SplitSource<OP>
+where
+    OP: Node<Value = Arr, InputGradient = Arr>,
+{
+    fn forward(&self) {
+        if self.counter.forward() == ForwardAction::Cached {
+            return;
+        }
+
+        self.operand.forward();
+    }
+
+    fn backward(&self, gradient: &Arr, start: usize, end: usize) {
+        {
+            let mut operand_gradient = self.operand_gradient.borrow_mut();
+
+            // Pieces that are not part of the graph never call backward,
+            // so their blocks must be zeroed here.
+            if self.counter.backward() == BackwardAction::Set {
+                operand_gradient.fill(0.0);
+            }
+
+            operand_gradient
+                .slice_axis_mut(self.axis, ndarray::Slice::from(start..end))
+                .add_assign(gradient);
+        }
+
+        if self.counter.recurse_backward() {
+            self.operand.backward(&self.operand_gradient.borrow());
+        }
+    }
+
+    fn clear(&self) {
+        if !self.counter.is_zero() {
+            self.operand.clear();
+            self.counter.clear();
+        }
+    }
+}
+
+/// One of the consecutive pieces that a variable is split into.
+#[derive(Debug)]
+pub struct SplitNode<OP> {
+    start: usize,
+    end: usize,
+    value: RefCell<Arr>,
+    gradient: RefCell<Arr>,
+    source: Rc<SplitSource<OP>>,
+    needs_gradient: bool,
+    counter: PassCounter,
+}
+
+impl<OP> SplitNode<OP>
+where
+    OP: Node<Value = Arr, InputGradient = Arr>,
+{
+    pub fn new(operand: Rc<OP>, sizes: &[usize], axis: ndarray::Axis) -> Vec<Self> {
+        check_axis(axis);
+
+        let length = operand.value().len_of(axis);
+        assert_eq!(
+            sizes.iter().sum::<usize>(),
+            length,
+            "Split sizes must add up to the length of the split axis."
+        );
+
+        let needs_gradient = operand.needs_gradient();
+        let operand_gradient = operand.value().deref() * 0.0;
+        let source = Rc::new(SplitSource {
+            axis: axis,
+            operand_gradient: RefCell::new(operand_gradient),
+            operand: operand,
+            counter: PassCounter::default(),
+        });
+
+        block_offsets(sizes.iter().cloned())
+            .into_iter()
+            .map(|(start, end)| {
+                let value = source
+                    .operand
+                    .value()
+                    .slice_axis(axis, ndarray::Slice::from(start..end))
+                    .to_owned();
+                let gradient = &value * 0.0;
+
+                SplitNode {
+                    start: start,
+                    end: end,
+                    value: RefCell::new(value),
+                    gradient: RefCell::new(gradient),
+                    source: Rc::clone(&source),
+                    needs_gradient: needs_gradient,
+                    counter: PassCounter::default(),
+                }
+            })
+            .collect()
+    }
+}
+
+impl<OP> Node for SplitNode<OP>
+where
+    OP: Node<Value = Arr, InputGradient = Arr>,
+{
+    type Value = Arr;
+    type InputGradient = Arr;
+    fn forward(&self) {
+        if self.counter.forward() == ForwardAction::Cached {
+            return;
+        }
+
+        self.source.forward();
+
+        self.value.borrow_mut().assign(
+            &self
+                .source
+                .operand
+                .value()
+                .slice_axis(self.source.axis, ndarray::Slice::from(self.start..self.end)),
+        );
+    }
+    fn backward(&self, gradient: &Ref<Self::InputGradient>) {
+        match self.counter.backward() {
+            BackwardAction::Set => self.gradient.borrow_mut().assign(gradient.deref()),
+            BackwardAction::Increment => self.gradient.borrow_mut().add_assign(gradient.deref()),
+        }
+
+        if self.counter.recurse_backward() {
+            self.source
+                .backward(&self.gradient.borrow(), self.start, self.end);
+        }
+    }
+
+    fn value(&self) -> Bor<Self::Value> {
+        Bor::RefGuard(self.value.borrow())
+    }
+    fn needs_gradient(&self) -> bool {
+        self.needs_gradient
+    }
+    fn clear(&self) {
+        if !self.counter.is_zero() {
+            self.source.clear();
+            self.counter.clear();
+        }
+    }
+}
+
 /// Input node for the graph.
 #[derive(Debug)]
 pub struct InputNode {
@@ -877,7 +1265,15 @@ where
 {
     pub fn new(lhs: Rc<LHS>, rhs: Rc<RHS>) -> Self {
         let needs_gradient = lhs.needs_gradient() || rhs.needs_gradient();
-        let value = lhs.value().deref() - rhs.value().deref();
+        let shape = broadcast_shape(lhs.value().shape(), rhs.value().shape());
+
+        let mut value = Arr::zeros(shape);
+        broadcast_binary_op(
+            &mut value,
+            lhs.value().deref(),
+            rhs.value().deref(),
+            |x, y| x - y,
+        );
 
         let rhs_gradient = rhs.value().deref() * 0.0;
         let lhs_gradient = lhs.value().deref() * 0.0;
@@ -910,16 +1306,40 @@ where
         self.rhs.forward();
 
         let mut dest = self.value.borrow_mut();
+        let lhs_value = self.lhs.value();
+        let rhs_value = self.rhs.value();
 
-        numerics::sub(
-            self.lhs.value().deref(),
-            self.rhs.value().deref(),
-            dest.deref_mut(),
-        );
+        if lhs_value.shape() == rhs_value.shape() {
+            numerics::sub(lhs_value.deref(), rhs_value.deref(), dest.deref_mut());
+        } else {
+            broadcast_binary_op(
+                dest.deref_mut(),
+                lhs_value.deref(),
+                rhs_value.deref(),
+                |x, y| x - y,
+            );
+        }
     }
 
     fn backward(&self, gradient: &Ref<Self::InputGradient>) {
+        let is_broadcast = self.lhs_gradient.borrow().shape() != gradient.shape()
+            || self.rhs_gradient.borrow().shape() != gradient.shape();
+
         match self.counter.backward() {
+            ref action if is_broadcast => {
+                reduce_broadcast_gradient(
+                    self.lhs_gradient.borrow_mut().deref_mut(),
+                    gradient.deref(),
+                    1.0,
+                    action,
+                );
+                reduce_broadcast_gradient(
+                    self.rhs_gradient.borrow_mut().deref_mut(),
+                    gradient.deref(),
+                    -1.0,
+                    action,
+                );
+            }
             BackwardAction::Set => {
                 let mut rhs_gradient = self.rhs_gradient.borrow_mut();
 
@@ -969,6 +1389,7 @@ where
 #[derive(Debug)]
 pub struct MulNode<LHS, RHS> {
     value: RefCell<Arr>,
+    gradient: RefCell<Arr>,
     lhs_gradient: RefCell<Arr>,
     rhs_gradient: RefCell<Arr>,
     lhs: Rc<LHS>,
@@ -984,13 +1405,23 @@ where
 {
     pub fn new(lhs: Rc<LHS>, rhs: Rc<RHS>) -> Self {
         let needs_gradient = lhs.needs_gradient() || rhs.needs_gradient();
-        let value = lhs.value().deref() * rhs.value().deref();
+        let shape = broadcast_shape(lhs.value().shape(), rhs.value().shape());
+
+        let mut value = Arr::zeros(shape);
+        broadcast_binary_op(
+            &mut value,
+            lhs.value().deref(),
+            rhs.value().deref(),
+            |x, y| x * y,
+        );
 
-        let lhs_gradient = &value * 0.0;
-        let rhs_gradient = &value * 0.0;
+        let gradient = &value * 0.0;
+        let lhs_gradient = lhs.value().deref() * 0.0;
+        let rhs_gradient = rhs.value().deref() * 0.0;
 
         MulNode {
             value: RefCell::new(value),
+            gradient: RefCell::new(gradient),
             lhs_gradient: RefCell::new(lhs_gradient),
             rhs_gradient: RefCell::new(rhs_gradient),
             lhs: lhs,
@@ -1017,19 +1448,60 @@ where
         self.rhs.forward();
 
         let mut dest = self.value.borrow_mut();
+        let lhs_value = self.lhs.value();
+        let rhs_value = self.rhs.value();
 
-        numerics::mul(
-            self.lhs.value().deref(),
-            self.rhs.value().deref(),
-            dest.deref_mut(),
-        );
+        if lhs_value.shape() == rhs_value.shape() {
+            numerics::mul(lhs_value.deref(), rhs_value.deref(), dest.deref_mut());
+        } else {
+            broadcast_binary_op(
+                dest.deref_mut(),
+                lhs_value.deref(),
+                rhs_value.deref(),
+                |x, y| x * y,
+            );
+        }
     }
     fn backward(&self, gradient: &Ref<Self::InputGradient>) {
+        let is_broadcast = self.lhs_gradient.borrow().shape() != gradient.shape()
+            || self.rhs_gradient.borrow().shape() != gradient.shape();
+
         match self.counter.backward() {
-            BackwardAction::Set => {
-                let mut lhs_gradient = self.lhs_gradient.borrow_mut();
+            ref action if is_broadcast => {
+                let lhs_value = self.lhs.value();
+                let rhs_value = self.rhs.value();
+                let mut broadcast_gradient = self.gradient.borrow_mut();
 
-                numerics::mul(
+                broadcast_binary_op(
+                    broadcast_gradient.deref_mut(),
+                    gradient.deref(),
+                    rhs_value.deref(),
+                    |grad, rhs| grad * rhs,
+                );
+                reduce_broadcast_gradient(
+                    self.lhs_gradient.borrow_mut().deref_mut(),
+                    broadcast_gradient.deref(),
+                    1.0,
+                    action,
+                );
+
+                broadcast_binary_op(
+                    broadcast_gradient.deref_mut(),
+                    gradient.deref(),
+                    lhs_value.deref(),
+                    |grad, lhs| grad * lhs,
+                );
+                reduce_broadcast_gradient(
+                    self.rhs_gradient.borrow_mut().deref_mut(),
+                    broadcast_gradient.deref(),
+                    1.0,
+                    action,
+                );
+            }
+            BackwardAction::Set => {
+                let mut lhs_gradient = self.lhs_gradient.borrow_mut();
+
+                numerics::mul(
                     self.rhs.value().deref(),
                     gradient.deref(),
                     lhs_gradient.deref_mut(),
@@ -1083,6 +1555,7 @@ where
 #[derive(Debug)]
 pub struct DivNode<LHS, RHS> {
     value: RefCell<Arr>,
+    gradient: RefCell<Arr>,
     lhs_gradient: RefCell<Arr>,
     rhs_gradient: RefCell<Arr>,
     lhs: Rc<LHS>,
@@ -1098,13 +1571,23 @@ where
 {
     pub fn new(lhs: Rc<LHS>, rhs: Rc<RHS>) -> Self {
         let needs_gradient = lhs.needs_gradient() || rhs.needs_gradient();
-        let value = lhs.value().deref() / rhs.value().deref();
+        let shape = broadcast_shape(lhs.value().shape(), rhs.value().shape());
+
+        let mut value = Arr::zeros(shape);
+        broadcast_binary_op(
+            &mut value,
+            lhs.value().deref(),
+            rhs.value().deref(),
+            |x, y| x / y,
+        );
 
-        let lhs_gradient = &value * 0.0;
-        let rhs_gradient = &value * 0.0;
+        let gradient = &value * 0.0;
+        let lhs_gradient = lhs.value().deref() * 0.0;
+        let rhs_gradient = rhs.value().deref() * 0.0;
 
         DivNode {
             value: RefCell::new(value),
+            gradient: RefCell::new(gradient),
             lhs_gradient: RefCell::new(lhs_gradient),
             rhs_gradient: RefCell::new(rhs_gradient),
             lhs: lhs,
@@ -1131,15 +1614,53 @@ where
         self.rhs.forward();
 
         let mut dest = self.value.borrow_mut();
+        let lhs_value = self.lhs.value();
+        let rhs_value = self.rhs.value();
 
-        numerics::div(
-            self.lhs.value().deref(),
-            self.rhs.value().deref(),
-            dest.deref_mut(),
-        );
+        if lhs_value.shape() == rhs_value.shape() {
+            numerics::div(lhs_value.deref(), rhs_value.deref(), dest.deref_mut());
+        } else {
+            broadcast_binary_op(
+                dest.deref_mut(),
+                lhs_value.deref(),
+                rhs_value.deref(),
+                |x, y| x / y,
+            );
+        }
     }
     fn backward(&self, gradient: &Ref<Self::InputGradient>) {
+        let is_broadcast = self.lhs_gradient.borrow().shape() != gradient.shape()
+            || self.rhs_gradient.borrow().shape() != gradient.shape();
+
         match self.counter.backward() {
+            ref action if is_broadcast => {
+                let lhs_value = self.lhs.value();
+                let rhs_value = self.rhs.value();
+                let mut broadcast_gradient = self.gradient.borrow_mut();
+
+                broadcast_binary_op(
+                    broadcast_gradient.deref_mut(),
+                    gradient.deref(),
+                    rhs_value.deref(),
+                    |grad, rhs| grad / rhs,
+                );
+                reduce_broadcast_gradient(
+                    self.lhs_gradient.borrow_mut().deref_mut(),
+                    broadcast_gradient.deref(),
+                    1.0,
+                    action,
+                );
+
+                // d(lhs / rhs) / d(rhs) = -(grad / rhs) * lhs / rhs
+                broadcast_gradient.zip_mut_with(lhs_value.deref(), |x, &lhs| *x *= -lhs);
+                broadcast_gradient.zip_mut_with(rhs_value.deref(), |x, &rhs| *x /= rhs);
+                reduce_broadcast_gradient(
+                    self.rhs_gradient.borrow_mut().deref_mut(),
+                    broadcast_gradient.deref(),
+                    1.0,
+                    action,
+                );
+            }
             BackwardAction::Set => {
                 let mut lhs_gradient = self.lhs_gradient.borrow_mut();
                 let rhs_value = self.rhs.value();
@@ -2135,29 +2656,306 @@ where
     }
 }
 
+/// A differentiable elementwise function `y = f(x)`.
+pub trait ElementwiseFunction: fmt::Debug + Copy + 'static {
+    /// Compute `f(x)`.
+    fn value(&self, x: f32) -> f32;
+    /// Compute `f'(x)`, given both the input `x` and the output `y = f(x)`.
+    fn derivative(&self, x: f32, y: f32) -> f32;
+}
+
+#[derive(Debug, Clone, Copy)]
+pub struct Abs;
+
+impl ElementwiseFunction for Abs {
+    fn value(&self, x: f32) -> f32 {
+        x.abs()
+    }
+    fn derivative(&self, x: f32, _: f32) -> f32 {
+        if x > 0.0 {
+            1.0
+        } else if x < 0.0 {
+            -1.0
+        } else {
+            0.0
+        }
+    }
+}
+
+#[derive(Debug, Clone, Copy)]
+pub struct Sqrt;
+
+impl ElementwiseFunction for Sqrt {
+    fn value(&self, x: f32) -> f32 {
+        x.sqrt()
+    }
+    fn derivative(&self, _: f32, y: f32) -> f32 {
+        0.5 / y
+    }
+}
+
+#[derive(Debug, Clone, Copy)]
+pub struct Powf(pub f32);
+
+impl ElementwiseFunction for Powf {
+    fn value(&self, x: f32) -> f32 {
+        x.powf(self.0)
+    }
+    fn derivative(&self, x: f32, _: f32) -> f32 {
+        self.0 * x.powf(self.0 - 1.0)
+    }
+}
+
+#[derive(Debug, Clone, Copy)]
+pub struct Reciprocal;
+
+impl ElementwiseFunction for Reciprocal {
+    fn value(&self, x: f32) -> f32 {
+        1.0 / x
+    }
+    fn derivative(&self, _: f32, y: f32) -> f32 {
+        -y * y
+    }
+}
+
+#[derive(Debug, Clone, Copy)]
+pub struct Sin;
+
+impl ElementwiseFunction for Sin {
+    fn value(&self, x: f32) -> f32 {
+        x.sin()
+    }
+    fn derivative(&self, x: f32, _: f32) -> f32 {
+        x.cos()
+    }
+}
+
+#[derive(Debug, Clone, Copy)]
+pub struct Cos;
+
+impl ElementwiseFunction for Cos {
+    fn value(&self, x: f32) -> f32 {
+        x.cos()
+    }
+    fn derivative(&self, x: f32, _: f32) -> f32 {
+        -x.sin()
+    }
+}
+
+#[derive(Debug, Clone, Copy)]
+pub struct Softplus;
+
+impl ElementwiseFunction for Softplus {
+    fn value(&self, x: f32) -> f32 {
+        // ln(1 + e^x) = max(x, 0) + ln(1 + e^-|x|), which neither
+        // overflows for large x nor loses precision for very negative x.
+        x.max(0.0) + (-x.abs()).exp().ln_1p()
+    }
+    fn derivative(&self, x: f32, _: f32) -> f32 {
+        numerics::sigmoid(x)
+    }
+}
+
+#[derive(Debug, Clone, Copy)]
+pub struct Log1p;
+
+impl ElementwiseFunction for Log1p {
+    fn value(&self, x: f32) -> f32 {
+        x.ln_1p()
+    }
+    fn derivative(&self, x: f32, _: f32) -> f32 {
+        1.0 / (1.0 + x)
+    }
+}
+
+#[derive(Debug, Clone, Copy)]
+pub struct LeakyRelu(pub f32);
+
+impl ElementwiseFunction for LeakyRelu {
+    fn value(&self, x: f32) -> f32 {
+        if x > 0.0 {
+            x
+        } else {
+            self.0 * x
+        }
+    }
+    fn derivative(&self, x: f32, _: f32) -> f32 {
+        if x > 0.0 {
+            1.0
+        } else {
+            self.0
+        }
+    }
+}
+
+#[derive(Debug, Clone, Copy)]
+pub struct Elu(pub f32);
+
+impl ElementwiseFunction for Elu {
+    fn value(&self, x: f32) -> f32 {
+        if x > 0.0 {
+            x
+        } else {
+            self.0 * (numerics::exp(x) - 1.0)
+        }
+    }
+    fn derivative(&self, x: f32, y: f32) -> f32 {
+        if x > 0.0 {
+            1.0
+        } else {
+            y + self.0
+        }
+    }
+}
+
+const SELU_ALPHA: f32 = 1.6732632;
+const SELU_SCALE: f32 = 1.050701;
+
+#[derive(Debug, Clone, Copy)]
+pub struct Selu;
+
+impl ElementwiseFunction for Selu {
+    fn value(&self, x: f32) -> f32 {
+        if x > 0.0 {
+            SELU_SCALE * x
+        } else {
+            SELU_SCALE * SELU_ALPHA * (numerics::exp(x) - 1.0)
+        }
+    }
+    fn derivative(&self, x: f32, y: f32) -> f32 {
+        if x > 0.0 {
+            SELU_SCALE
+        } else {
+            y + SELU_SCALE * SELU_ALPHA
+        }
+    }
+}
+
+#[derive(Debug, Clone, Copy)]
+pub struct Gelu;
+
+impl ElementwiseFunction for Gelu {
+    fn value(&self, x: f32) -> f32 {
+        0.5 * x * (1.0 + numerics::erf(x * std::f32::consts::FRAC_1_SQRT_2))
+    }
+    fn derivative(&self, x: f32, _: f32) -> f32 {
+        let cdf = 0.5 * (1.0 + numerics::erf(x * std::f32::consts::FRAC_1_SQRT_2));
+        let pdf = numerics::exp(-0.5 * x * x)
+            * 0.5
+            * std::f32::consts::FRAC_2_SQRT_PI
+            * std::f32::consts::FRAC_1_SQRT_2;
+
+        cdf + x * pdf
+    }
+}
+
+const GELU_TANH_SCALE: f32 = 0.7978846;
+const GELU_TANH_CUBIC: f32 = 0.044715;
+
+#[derive(Debug, Clone, Copy)]
+pub struct GeluTanh;
+
+impl ElementwiseFunction for GeluTanh {
+    fn value(&self, x: f32) -> f32 {
+        let inner = GELU_TANH_SCALE * (x + GELU_TANH_CUBIC * x.powi(3));
+
+        0.5 * x * (1.0 + numerics::tanh(inner))
+    }
+    fn derivative(&self, x: f32, _: f32) -> f32 {
+        let inner = GELU_TANH_SCALE * (x + GELU_TANH_CUBIC * x.powi(3));
+        let tanh = numerics::tanh(inner);
+        let inner_derivative = GELU_TANH_SCALE * (1.0 + 3.0 * GELU_TANH_CUBIC * x.powi(2));
+
+        0.5 * (1.0 + tanh) + 0.5 * x * (1.0 - tanh.powi(2)) * inner_derivative
+    }
+}
+
+#[derive(Debug, Clone, Copy)]
+pub struct Swish;
+
+impl ElementwiseFunction for Swish {
+    fn value(&self, x: f32) -> f32 {
+        x * numerics::sigmoid(x)
+    }
+    fn derivative(&self, x: f32, y: f32) -> f32 {
+        let sigmoid = numerics::sigmoid(x);
+
+        sigmoid + y * (1.0 - sigmoid)
+    }
+}
+
+#[derive(Debug, Clone, Copy)]
+pub struct Clamp {
+    min: f32,
+    max: f32,
+}
+
+impl Clamp {
+    pub fn new(min: f32, max: f32) -> Self {
+        assert!(
+            min <= max,
+            "Clamp minimum {} must not exceed the maximum {}.",
+            min,
+            max
+        );
+
+        Clamp { min: min, max: max }
+    }
+}
+
+impl ElementwiseFunction for Clamp {
+    fn value(&self, x: f32) -> f32 {
+        clamp(x, self.min, self.max)
+    }
+    fn derivative(&self, x: f32, _: f32) -> f32 {
+        if x >= self.min && x <= self.max {
+            1.0
+        } else {
+            0.0
+        }
+    }
+}
+
+/// Clamps in the forward pass, but passes gradients through
+/// unchanged in the backward pass.
+#[derive(Debug, Clone, Copy)]
+pub struct StraightThroughClamp(pub Clamp);
+
+impl ElementwiseFunction for StraightThroughClamp {
+    fn value(&self, x: f32) -> f32 {
+        self.0.value(x)
+    }
+    fn derivative(&self, _: f32, _: f32) -> f32 {
+        1.0
+    }
+}
+
 #[derive(Debug)]
-pub struct TransposeNode<OP> {
+pub struct ElementwiseNode<OP, F> {
+    function: F,
     value: RefCell<Arr>,
-    gradient: RefCell<Arr>,
+    operand_gradient: RefCell<Arr>,
     operand: Rc<OP>,
     needs_gradient: bool,
     counter: PassCounter,
 }
 
-impl<OP> TransposeNode<OP>
+pub type ClampNode<OP> = ElementwiseNode<OP, Clamp>;
+
+impl<OP, F> ElementwiseNode<OP, F>
 where
     OP: Node<Value = Arr>,
+    F: ElementwiseFunction,
 {
-    pub fn new(operand: Rc<OP>) -> Self {
+    pub fn new(operand: Rc<OP>, function: F) -> Self {
+        let value = operand.value().map(|&x| function.value(x));
+        let gradient = &value * 0.0;
         let needs_gradient = operand.needs_gradient();
-        let mut value = Arr::zeros((operand.value().cols(), operand.value().rows()));
-        value.assign(&operand.value().t());
-        let value = RefCell::new(value);
-        let gradient = RefCell::new(operand.value().deref() * 0.0);
 
-        TransposeNode {
-            value: value,
-            gradient: gradient,
+        ElementwiseNode {
+            function: function,
+            value: RefCell::new(value),
+            operand_gradient: RefCell::new(gradient),
             operand: operand,
             needs_gradient: needs_gradient,
             counter: PassCounter::default(),
@@ -2165,9 +2963,10 @@ where
     }
 }
 
-impl<OP> Node for TransposeNode<OP>
+impl<OP, F> Node for ElementwiseNode<OP, F>
 where
     OP: Node<Value = Arr, InputGradient = Arr>,
+    F: ElementwiseFunction,
 {
     type Value = Arr;
     type InputGradient = Arr;
@@ -2177,20 +2976,44 @@ where
         }
 
         self.operand.forward();
-        self.value.borrow_mut().assign(&self.operand.value().t());
+
+        let function = self.function;
+        let mut dest = self.value.borrow_mut();
+
+        numerics::map_assign(dest.deref_mut(), self.operand.value().deref(), |x| {
+            function.value(x)
+        });
     }
+
     fn backward(&self, gradient: &Ref<Self::InputGradient>) {
-        match self.counter.backward() {
-            BackwardAction::Set => {
-                self.gradient.borrow_mut().assign(&gradient.t());
-            }
-            BackwardAction::Increment => {
-                self.gradient.borrow_mut().slice_add_assign(&gradient.t());
+        {
+            let mut operand_gradient = self.operand_gradient.borrow_mut();
+            let operand_value = self.operand.value();
+            let value = self.value.borrow();
+
+            let iter = izip!(
+                operand_gradient.fast_slice_mut().iter_mut(),
+                operand_value.fast_slice().iter(),
+                value.fast_slice().iter(),
+                gradient.fast_slice().iter()
+            );
+
+            match self.counter.backward() {
+                BackwardAction::Set => {
+                    for (dest, &x, &y, &grad) in iter {
+                        *dest = grad * self.function.derivative(x, y);
+                    }
+                }
+                BackwardAction::Increment => {
+                    for (dest, &x, &y, &grad) in iter {
+                        *dest += grad * self.function.derivative(x, y);
+                    }
+                }
             }
         }
 
         if self.counter.recurse_backward() {
-            self.operand.backward(&self.gradient.borrow());
+            self.operand.backward(&self.operand_gradient.borrow());
         }
     }
 
@@ -2211,42 +3034,28 @@ where
 }
 
 #[derive(Debug)]
-pub struct SoftmaxNode<OP> {
+pub struct TransposeNode<OP> {
     value: RefCell<Arr>,
-    jacobian: RefCell<Arr>,
-    operand_gradient: RefCell<Arr>,
+    gradient: RefCell<Arr>,
     operand: Rc<OP>,
     needs_gradient: bool,
     counter: PassCounter,
 }
 
-impl<OP> SoftmaxNode<OP>
+impl<OP> TransposeNode<OP>
 where
     OP: Node<Value = Arr>,
 {
     pub fn new(operand: Rc<OP>) -> Self {
-        let value = {
-            let max = operand
-                .value()
-                .deref()
-                .as_slice()
-                .unwrap()
-                .iter()
-                .fold(std::f32::MIN, |x, y| x.max(*y));
-            let numerator = operand.value().map(|x| numerics::exp(x - max));
-            let denominator = numerator.scalar_sum();
-
-            numerator / denominator
-        };
-
-        let gradient = &value * 0.0;
         let needs_gradient = operand.needs_gradient();
-        let dim = value.shape()[1];
+        let mut value = Arr::zeros((operand.value().cols(), operand.value().rows()));
+        value.assign(&operand.value().t());
+        let value = RefCell::new(value);
+        let gradient = RefCell::new(operand.value().deref() * 0.0);
 
-        SoftmaxNode {
-            value: RefCell::new(value),
-            jacobian: RefCell::new(ndarray::Array2::zeros((dim, dim))),
-            operand_gradient: RefCell::new(gradient),
+        TransposeNode {
+            value: value,
+            gradient: gradient,
             operand: operand,
             needs_gradient: needs_gradient,
             counter: PassCounter::default(),
@@ -2254,7 +3063,7 @@ where
     }
 }
 
-impl<OP> Node for SoftmaxNode<OP>
+impl<OP> Node for TransposeNode<OP>
 where
     OP: Node<Value = Arr, InputGradient = Arr>,
 {
@@ -2266,70 +3075,31 @@ where
         }
 
         self.operand.forward();
-        let mut dest = self.value.borrow_mut();
-        dest.slice_assign(self.operand.value().deref());
-
-        let max = self
-            .operand
-            .value()
-            .fast_slice()
-            .iter()
-            .fold(std::f32::MIN, |x, y| x.max(*y));
-        dest.map_inplace(|x| *x = numerics::exp(*x - max));
-        let denominator = dest.scalar_sum();
-        dest.map_inplace(|x| *x /= denominator);
+        self.value.borrow_mut().assign(&self.operand.value().t());
     }
     fn backward(&self, gradient: &Ref<Self::InputGradient>) {
-        // TODO: accumulate gradients
-        let value = self.value.borrow();
-        let mut jacobian = self.jacobian.borrow_mut();
-
-        let beta = match self.counter.backward() {
-            BackwardAction::Set => 0.0,
-            BackwardAction::Increment => 1.0,
-        };
-
-        for (row_idx, (mut row, row_val)) in jacobian
-            .genrows_mut()
-            .into_iter()
-            .zip(value.iter())
-            .enumerate()
-        {
-            for (col_idx, (grad, col_val)) in row
-                .as_slice_mut()
-                .unwrap()
-                .iter_mut()
-                .zip(value.as_slice().unwrap())
-                .enumerate()
-            {
-                if row_idx == col_idx {
-                    *grad = row_val * (1.0 - col_val);
-                } else {
-                    *grad = -row_val * col_val;
-                }
+        match self.counter.backward() {
+            BackwardAction::Set => {
+                self.gradient.borrow_mut().assign(&gradient.t());
+            }
+            BackwardAction::Increment => {
+                self.gradient.borrow_mut().slice_add_assign(&gradient.t());
             }
-        }
-
-        {
-            numerics::mat_mul(
-                1.0,
-                gradient,
-                jacobian.deref_mut(),
-                beta,
-                self.operand_gradient.borrow_mut().deref_mut(),
-            );
         }
 
         if self.counter.recurse_backward() {
-            self.operand.backward(&self.operand_gradient.borrow());
+            self.operand.backward(&self.gradient.borrow());
         }
     }
+
     fn value(&self) -> Bor<Self::Value> {
         Bor::RefGuard(self.value.borrow())
     }
+
     fn needs_gradient(&self) -> bool {
         self.needs_gradient
     }
+
     fn clear(&self) {
         if !self.counter.is_zero() {
             self.operand.clear();
@@ -2338,55 +3108,2605 @@ where
     }
 }
 
+/// Copies the (row-major) elements of its operand into a matrix of a
+/// different shape, and copies gradients back into the operand's shape.
+///
+/// Nodes own their values, so the copy cannot be avoided even when the
+/// operand is contiguous.
 #[derive(Debug)]
-pub struct LogSoftmaxNode<OP> {
+pub struct ReshapeNode<OP> {
     value: RefCell<Arr>,
-    operand_gradient: RefCell<Arr>,
+    gradient: RefCell<Arr>,
     operand: Rc<OP>,
     needs_gradient: bool,
     counter: PassCounter,
 }
 
-impl<OP> LogSoftmaxNode<OP>
+impl<OP> ReshapeNode<OP>
 where
     OP: Node<Value = Arr>,
 {
-    pub fn new(operand: Rc<OP>) -> Self {
+    pub fn new(operand: Rc<OP>, shape: (usize, usize)) -> Self {
+        assert_eq!(
+            operand.value().len(),
+            shape.0 * shape.1,
+            "Cannot reshape {:?} into {:?}.",
+            operand.value().dim(),
+            shape
+        );
+
+        let needs_gradient = operand.needs_gradient();
+        let mut value = Arr::zeros(shape);
+        value.slice_assign(operand.value().deref());
+        let gradient = operand.value().deref() * 0.0;
+
+        ReshapeNode {
+            value: RefCell::new(value),
+            gradient: RefCell::new(gradient),
+            operand: operand,
+            needs_gradient: needs_gradient,
+            counter: PassCounter::default(),
+        }
+    }
+}
+
+impl<OP> Node for ReshapeNode<OP>
+where
+    OP: Node<Value = Arr, InputGradient = Arr>,
+{
+    type Value = Arr;
+    type InputGradient = Arr;
+    fn forward(&self) {
+        if self.counter.forward() == ForwardAction::Cached {
+            return;
+        }
+
+        self.operand.forward();
+        self.value
+            .borrow_mut()
+            .slice_assign(self.operand.value().deref());
+    }
+    fn backward(&self, gradient: &Ref<Self::InputGradient>) {
+        match self.counter.backward() {
+            BackwardAction::Set => {
+                self.gradient.borrow_mut().slice_assign(gradient.deref());
+            }
+            BackwardAction::Increment => {
+                self.gradient
+                    .borrow_mut()
+                    .slice_add_assign(gradient.deref());
+            }
+        }
+
+        if self.counter.recurse_backward() {
+            self.operand.backward(&self.gradient.borrow());
+        }
+    }
+
+    fn value(&self) -> Bor<Self::Value> {
+        Bor::RefGuard(self.value.borrow())
+    }
+
+    fn needs_gradient(&self) -> bool {
+        self.needs_gradient
+    }
+
+    fn clear(&self) {
+        if !self.counter.is_zero() {
+            self.operand.clear();
+            self.counter.clear();
+        }
+    }
+}
+
+/// Passes the value of its operand through unchanged, but blocks
+/// gradients from flowing back into it.
+///
+/// The operand still receives a backward pass with a zero gradient:
+/// this keeps the pass counters of its subgraph (which may be shared
+/// with other parts of the graph) consistent.
+#[derive(Debug)]
+pub struct DetachNode<OP> {
+    zero_gradient: RefCell<Arr>,
+    operand: Rc<OP>,
+    counter: PassCounter,
+}
+
+impl<OP> DetachNode<OP>
+where
+    OP: Node<Value = Arr>,
+{
+    pub fn new(operand: Rc<OP>) -> Self {
+        let zero_gradient = operand.value().deref() * 0.0;
+
+        DetachNode {
+            zero_gradient: RefCell::new(zero_gradient),
+            operand: operand,
+            counter: PassCounter::default(),
+        }
+    }
+}
+
+impl<OP> Node for DetachNode<OP>
+where
+    OP: Node<Value = Arr, InputGradient = Arr>,
+{
+    type Value = Arr;
+    type InputGradient = Arr;
+    fn forward(&self) {
+        if self.counter.forward() == ForwardAction::Cached {
+            return;
+        }
+
+        self.operand.forward();
+    }
+    fn backward(&self, _: &Ref<Self::InputGradient>) {
+        self.counter.backward();
+
+        if self.counter.recurse_backward() {
+            self.operand.backward(&self.zero_gradient.borrow());
+        }
+    }
+
+    fn value(&self) -> Bor<Self::Value> {
+        self.operand.value()
+    }
+
+    fn needs_gradient(&self) -> bool {
+        false
+    }
+
+    fn clear(&self) {
+        if !self.counter.is_zero() {
+            self.operand.clear();
+            self.counter.clear();
+        }
+    }
+}
+
+/// A user-defined differentiable operation on any number of inputs.
+///
+/// Wrap it in a graph node with `Variable::custom`, which takes care
+/// of caching, pass counting and parameter tracking.
+///
+/// ```rust
+/// # extern crate wyrm;
+/// # use wyrm::*;
+/// # fn main() {
+/// /// Computes `x * y^2`.
+/// #[derive(Debug)]
+/// struct MulSquare;
+///
+/// impl CustomOp for MulSquare {
+///     fn forward(&self, inputs: &[&Arr]) -> Arr {
+///         inputs[0] * &inputs[1].map(|y| y * y)
+///     }
+///     fn backward(&self, inputs: &[&Arr], _output: &Arr, gradient: &Arr) -> Vec<Arr> {
+///         let (x, y) = (inputs[0], inputs[1]);
+///
+///         vec![gradient * &y.map(|y| y * y), gradient * x * y * 2.0]
+///     }
+/// }
+///
+/// let x = ParameterNode::new(Arr::ones((2, 3)));
+/// let y = ParameterNode::new(Arr::ones((2, 3)) * 2.0);
+///
+/// let mut z = Variable::custom(&[x.clone(), y.clone()], MulSquare).scalar_sum();
+///
+/// z.forward();
+/// z.backward(1.0);
+///
+/// assert_eq!(z.value()[(0, 0)], 24.0);
+/// assert_eq!(y.gradient()[(0, 0)], 4.0);
+/// # }
+/// ```
+pub trait CustomOp: fmt::Debug + 'static {
+    /// Compute the output from the values of the inputs.
+    fn forward(&self, inputs: &[&Arr]) -> Arr;
+    /// Given the values of the inputs, the output, and the gradient of the
+    /// output, compute the gradients of the inputs: one per input, of the
+    /// same shape as that input.
+    fn backward(&self, inputs: &[&Arr], output: &Arr, gradient: &Arr) -> Vec<Arr>;
+}
+
+/// Node wrapping a `CustomOp`.
+#[derive(Debug)]
+pub struct CustomNode<O> {
+    op: O,
+    value: RefCell<Arr>,
+    operand_gradients: Vec<RefCell<Arr>>,
+    operands: Vec<Rc<Node<Value = Arr, InputGradient = Arr>>>,
+    needs_gradient: bool,
+    counter: PassCounter,
+}
+
+impl<O> CustomNode<O>
+where
+    O: CustomOp,
+{
+    pub fn new(operands: Vec<Rc<Node<Value = Arr, InputGradient = Arr>>>, op: O) -> Self {
+        let value = {
+            let values: Vec<_> = operands.iter().map(|x| x.value()).collect();
+            let inputs: Vec<&Arr> = values.iter().map(|x| x.deref()).collect();
+
+            // The op may return arrays in any memory layout.
+            let output = op.forward(&inputs);
+            let mut value = Arr::zeros(output.dim());
+            value.assign(&output);
+
+            value
+        };
+
+        let needs_gradient = operands.iter().any(|x| x.needs_gradient());
+        let operand_gradients = operands
+            .iter()
+            .map(|x| RefCell::new(x.value().deref() * 0.0))
+            .collect();
+
+        CustomNode {
+            op: op,
+            value: RefCell::new(value),
+            operand_gradients: operand_gradients,
+            operands: operands,
+            needs_gradient: needs_gradient,
+            counter: PassCounter::default(),
+        }
+    }
+}
+
+impl<O> Node for CustomNode<O>
+where
+    O: CustomOp,
+{
+    type Value = Arr;
+    type InputGradient = Arr;
+    fn forward(&self) {
+        if self.counter.forward() == ForwardAction::Cached {
+            return;
+        }
+
+        for operand in &self.operands {
+            operand.forward();
+        }
+
+        let values: Vec<_> = self.operands.iter().map(|x| x.value()).collect();
+        let inputs: Vec<&Arr> = values.iter().map(|x| x.deref()).collect();
+
+        let output = self.op.forward(&inputs);
+        let mut value = self.value.borrow_mut();
+
+        assert_eq!(
+            value.dim(),
+            output.dim(),
+            "Custom op output must keep the same shape across passes."
+        );
+
+        value.assign(&output);
+    }
+    fn backward(&self, gradient: &Ref<Self::InputGradient>) {
+        let action = self.counter.backward();
+
+        {
+            let values: Vec<_> = self.operands.iter().map(|x| x.value()).collect();
+            let inputs: Vec<&Arr> = values.iter().map(|x| x.deref()).collect();
+
+            let gradients =
+                self.op
+                    .backward(&inputs, self.value.borrow().deref(), gradient.deref());
+
+            assert_eq!(
+                gradients.len(),
+                self.operands.len(),
+                "Custom op must return one gradient per input."
+            );
+
+            for (operand_gradient, gradient) in self.operand_gradients.iter().zip(gradients.iter())
+            {
+                let mut operand_gradient = operand_gradient.borrow_mut();
+
+                assert_eq!(
+                    operand_gradient.dim(),
+                    gradient.dim(),
+                    "Custom op gradients must have the same shape as their inputs."
+                );
+
+                // Gradients may come in any memory layout, so
+                // use the layout-aware (if slower) operations.
+                match action {
+                    BackwardAction::Set => operand_gradient.assign(gradient),
+                    BackwardAction::Increment => *operand_gradient += gradient,
+                }
+            }
+        }
+
+        if self.counter.recurse_backward() {
+            for (operand, operand_gradient) in
+                self.operands.iter().zip(self.operand_gradients.iter())
+            {
+                operand.backward(&operand_gradient.borrow());
+            }
+        }
+    }
+
+    fn value(&self) -> Bor<Self::Value> {
+        Bor::RefGuard(self.value.borrow())
+    }
+    fn needs_gradient(&self) -> bool {
+        self.needs_gradient
+    }
+    fn clear(&self) {
+        if !self.counter.is_zero() {
+            for operand in &self.operands {
+                operand.clear();
+            }
+            self.counter.clear();
+        }
+    }
+}
+
+/// Elementwise selection: takes elements of `lhs` where `mask` is non-zero
+/// and elements of `rhs` elsewhere. Gradients only flow to the selected side.
+#[derive(Debug)]
+pub struct WhereNode<LHS, RHS> {
+    value: RefCell<Arr>,
+    gradient: RefCell<Arr>,
+    lhs_gradient: RefCell<Arr>,
+    rhs_gradient: RefCell<Arr>,
+    mask: Rc<InputNode>,
+    lhs: Rc<LHS>,
+    rhs: Rc<RHS>,
+    needs_gradient: bool,
+    counter: PassCounter,
+}
+
+impl<LHS, RHS> WhereNode<LHS, RHS>
+where
+    LHS: Node<Value = Arr>,
+    RHS: Node<Value = Arr>,
+{
+    pub fn new(mask: Rc<InputNode>, lhs: Rc<LHS>, rhs: Rc<RHS>) -> Self {
+        assert_eq!(
+            lhs.value().shape(),
+            rhs.value().shape(),
+            "Operands must have the same shape."
+        );
+        assert_eq!(
+            mask.value().shape(),
+            lhs.value().shape(),
+            "Mask must have the same shape as the operands."
+        );
+
+        let mut value = lhs.value().deref() * 0.0;
+        Self::select(&mut value, &mask.value(), &lhs.value(), &rhs.value());
+
+        let needs_gradient = lhs.needs_gradient() || rhs.needs_gradient();
+        let gradient = &value * 0.0;
+        let lhs_gradient = &value * 0.0;
+        let rhs_gradient = &value * 0.0;
+
+        WhereNode {
+            value: RefCell::new(value),
+            gradient: RefCell::new(gradient),
+            lhs_gradient: RefCell::new(lhs_gradient),
+            rhs_gradient: RefCell::new(rhs_gradient),
+            mask: mask,
+            lhs: lhs,
+            rhs: rhs,
+            needs_gradient: needs_gradient,
+            counter: PassCounter::default(),
+        }
+    }
+
+    fn select(dest: &mut Arr, mask: &Arr, lhs: &Arr, rhs: &Arr) {
+        for (out, &mask, &lhs, &rhs) in izip!(
+            dest.fast_slice_mut(),
+            mask.fast_slice(),
+            lhs.fast_slice(),
+            rhs.fast_slice()
+        ) {
+            *out = if mask != 0.0 { lhs } else { rhs };
+        }
+    }
+}
+
+impl<LHS, RHS> Node for WhereNode<LHS, RHS>
+where
+    LHS: Node<Value = Arr, InputGradient = Arr>,
+    RHS: Node<Value = Arr, InputGradient = Arr>,
+{
+    type Value = Arr;
+    type InputGradient = Arr;
+    fn forward(&self) {
+        if self.counter.forward() == ForwardAction::Cached {
+            return;
+        }
+
+        self.mask.forward();
+        self.lhs.forward();
+        self.rhs.forward();
+
+        let mask = self.mask.value();
+
+        debug_assert_eq!(
+            mask.shape(),
+            self.gradient.borrow().shape(),
+            "Mask changed shape."
+        );
+
+        Self::select(
+            self.value.borrow_mut().deref_mut(),
+            &mask,
+            &self.lhs.value(),
+            &self.rhs.value(),
+        );
+    }
+    fn backward(&self, gradient: &Ref<Self::InputGradient>) {
+        match self.counter.backward() {
+            BackwardAction::Set => self.gradient.borrow_mut().slice_assign(gradient.deref()),
+            BackwardAction::Increment => self
+                .gradient
+                .borrow_mut()
+                .slice_add_assign(gradient.deref()),
+        }
+
+        if self.counter.recurse_backward() {
+            {
+                let mask = self.mask.value();
+                let gradient = self.gradient.borrow();
+                let mut lhs_gradient = self.lhs_gradient.borrow_mut();
+                let mut rhs_gradient = self.rhs_gradient.borrow_mut();
+
+                for (lhs_grad, rhs_grad, &mask, &grad) in izip!(
+                    lhs_gradient.fast_slice_mut(),
+                    rhs_gradient.fast_slice_mut(),
+                    mask.fast_slice(),
+                    gradient.fast_slice()
+                ) {
+                    if mask != 0.0 {
+                        *lhs_grad = grad;
+                        *rhs_grad = 0.0;
+                    } else {
+                        *lhs_grad = 0.0;
+                        *rhs_grad = grad;
+                    }
+                }
+            }
+
+            self.lhs.backward(&self.lhs_gradient.borrow());
+            self.rhs.backward(&self.rhs_gradient.borrow());
+        }
+    }
+
+    fn value(&self) -> Bor<Self::Value> {
+        Bor::RefGuard(self.value.borrow())
+    }
+
+    fn needs_gradient(&self) -> bool {
+        self.needs_gradient
+    }
+
+    fn clear(&self) {
+        if !self.counter.is_zero() {
+            self.mask.clear();
+            self.lhs.clear();
+            self.rhs.clear();
+            self.counter.clear();
+        }
+    }
+}
+
+thread_local! {
+    static TRAINING: Cell<bool> = const { Cell::new(true) };
+}
+
+/// Switch between training (the default) and evaluation mode. In evaluation
+/// mode, nodes such as dropout act as the identity.
+///
+/// Like the graphs it affects, the mode is local to the current thread.
+pub fn set_training(training: bool) {
+    TRAINING.with(|x| x.set(training));
+}
+
+/// Whether the current thread is in training mode.
+pub fn is_training() -> bool {
+    TRAINING.with(|x| x.get())
+}
+
+/// Inverted dropout: zeroes each element with probability `p`,
+/// and scales the remaining ones by `1 / (1 - p)`. A fresh mask
+/// is drawn on every forward pass in training mode.
+#[derive(Debug)]
+pub struct DropoutNode<OP> {
+    value: RefCell<Arr>,
+    mask: RefCell<Arr>,
+    gradient: RefCell<Arr>,
+    probability: f32,
+    rng: RefCell<XorShiftRng>,
+    operand: Rc<OP>,
+    needs_gradient: bool,
+    counter: PassCounter,
+}
+
+impl<OP> DropoutNode<OP>
+where
+    OP: Node<Value = Arr>,
+{
+    pub fn new<R: Rng>(operand: Rc<OP>, probability: f32, rng: &mut R) -> Self {
+        assert!(
+            (0.0..1.0).contains(&probability),
+            "Dropout probability must be in [0, 1)."
+        );
+
+        let rng = XorShiftRng::from_rng(rng).expect("Unable to seed dropout RNG.");
+        let value = operand.value().deref() * 0.0;
+        let mask = &value * 0.0;
+        let gradient = &value * 0.0;
+        let needs_gradient = operand.needs_gradient();
+
+        let node = DropoutNode {
+            value: RefCell::new(value),
+            mask: RefCell::new(mask),
+            gradient: RefCell::new(gradient),
+            probability: probability,
+            rng: RefCell::new(rng),
+            operand: operand,
+            needs_gradient: needs_gradient,
+            counter: PassCounter::default(),
+        };
+
+        node.sample();
+
+        node
+    }
+
+    fn sample(&self) {
+        let mut mask = self.mask.borrow_mut();
+
+        if is_training() {
+            let mut rng = self.rng.borrow_mut();
+            let scale = 1.0 / (1.0 - self.probability);
+
+            for mask in mask.fast_slice_mut() {
+                *mask = if rng.gen::<f32>() < self.probability {
+                    0.0
+                } else {
+                    scale
+                };
+            }
+        } else {
+            mask.fill(1.0);
+        }
+
+        for (value, &mask, &operand) in izip!(
+            self.value.borrow_mut().fast_slice_mut(),
+            mask.fast_slice(),
+            self.operand.value().fast_slice()
+        ) {
+            *value = mask * operand;
+        }
+    }
+}
+
+impl<OP> Node for DropoutNode<OP>
+where
+    OP: Node<Value = Arr, InputGradient = Arr>,
+{
+    type Value = Arr;
+    type InputGradient = Arr;
+    fn forward(&self) {
+        if self.counter.forward() == ForwardAction::Cached {
+            return;
+        }
+
+        self.operand.forward();
+        self.sample();
+    }
+    fn backward(&self, gradient: &Ref<Self::InputGradient>) {
+        {
+            let mut operand_gradient = self.gradient.borrow_mut();
+            let mask = self.mask.borrow();
+
+            match self.counter.backward() {
+                BackwardAction::Set => {
+                    for (dest, &mask, &grad) in izip!(
+                        operand_gradient.fast_slice_mut(),
+                        mask.fast_slice(),
+                        gradient.fast_slice()
+                    ) {
+                        *dest = mask * grad;
+                    }
+                }
+                BackwardAction::Increment => {
+                    for (dest, &mask, &grad) in izip!(
+                        operand_gradient.fast_slice_mut(),
+                        mask.fast_slice(),
+                        gradient.fast_slice()
+                    ) {
+                        *dest += mask * grad;
+                    }
+                }
+            }
+        }
+
+        if self.counter.recurse_backward() {
+            self.operand.backward(&self.gradient.borrow());
+        }
+    }
+
+    fn value(&self) -> Bor<Self::Value> {
+        Bor::RefGuard(self.value.borrow())
+    }
+
+    fn needs_gradient(&self) -> bool {
+        self.needs_gradient
+    }
+
+    fn clear(&self) {
+        if !self.counter.is_zero() {
+            self.operand.clear();
+            self.counter.clear();
+        }
+    }
+}
+
+/// Added to squared norms before taking the square root, keeping
+/// normalisation gradients finite near zero norm.
+const NORM_EPSILON: f32 = 1e-12;
+
+fn lane_dot(xs: ndarray::ArrayView1<f32>, ys: ndarray::ArrayView1<f32>) -> f32 {
+    match (xs.as_slice(), ys.as_slice()) {
+        (Some(xs), Some(ys)) => numerics::simd_dot(xs, ys),
+        _ => xs.dot(&ys),
+    }
+}
+
+/// Normalise the lanes of `source` along `axis` to unit L2 norm,
+/// writing them into `dest` and their norms into `norms`.
+fn l2_normalize_lanes(dest: &mut Arr, norms: &mut Arr, source: &Arr, axis: Axis) {
+    for (mut dest, norm, source) in
+        izip!(dest.lanes_mut(axis), norms.iter_mut(), source.lanes(axis))
+    {
+        *norm = (lane_dot(source.view(), source.view()) + NORM_EPSILON).sqrt();
+
+        for (dest, &source) in dest.iter_mut().zip(source.iter()) {
+            *dest = source / *norm;
+        }
+    }
+}
+
+/// Transform `gradient`, the gradient of lanes normalised by
+/// `l2_normalize_lanes`, into the gradient of the original lanes.
+fn l2_normalize_backward(gradient: &mut Arr, normalized: &Arr, norms: &Arr, axis: Axis) {
+    for (mut gradient, &norm, normalized) in izip!(
+        gradient.lanes_mut(axis),
+        norms.iter(),
+        normalized.lanes(axis)
+    ) {
+        let projection = lane_dot(gradient.view(), normalized.view());
+
+        for (gradient, &normalized) in gradient.iter_mut().zip(normalized.iter()) {
+            *gradient = (*gradient - normalized * projection) / norm;
+        }
+    }
+}
+
+/// Normalises the lanes of its operand along an axis to unit L2 norm.
+#[derive(Debug)]
+pub struct L2NormalizeNode<OP> {
+    axis: Axis,
+    value: RefCell<Arr>,
+    norms: RefCell<Arr>,
+    gradient: RefCell<Arr>,
+    operand_gradient: RefCell<Arr>,
+    operand: Rc<OP>,
+    needs_gradient: bool,
+    counter: PassCounter,
+}
+
+impl<OP> L2NormalizeNode<OP>
+where
+    OP: Node<Value = Arr>,
+{
+    pub fn new(operand: Rc<OP>, axis: Axis) -> Self {
+        check_axis(axis);
+
+        let mut value = operand.value().deref() * 0.0;
+        let mut norms = match axis {
+            Axis(0) => Arr::zeros((1, value.cols())),
+            _ => Arr::zeros((value.rows(), 1)),
+        };
+        l2_normalize_lanes(&mut value, &mut norms, &operand.value(), axis);
+
+        let gradient = &value * 0.0;
+        let operand_gradient = &value * 0.0;
+        let needs_gradient = operand.needs_gradient();
+
+        L2NormalizeNode {
+            axis: axis,
+            value: RefCell::new(value),
+            norms: RefCell::new(norms),
+            gradient: RefCell::new(gradient),
+            operand_gradient: RefCell::new(operand_gradient),
+            operand: operand,
+            needs_gradient: needs_gradient,
+            counter: PassCounter::default(),
+        }
+    }
+}
+
+impl<OP> Node for L2NormalizeNode<OP>
+where
+    OP: Node<Value = Arr, InputGradient = Arr>,
+{
+    type Value = Arr;
+    type InputGradient = Arr;
+    fn forward(&self) {
+        if self.counter.forward() == ForwardAction::Cached {
+            return;
+        }
+
+        self.operand.forward();
+
+        l2_normalize_lanes(
+            self.value.borrow_mut().deref_mut(),
+            self.norms.borrow_mut().deref_mut(),
+            &self.operand.value(),
+            self.axis,
+        );
+    }
+    fn backward(&self, gradient: &Ref<Self::InputGradient>) {
+        match self.counter.backward() {
+            BackwardAction::Set => self.gradient.borrow_mut().slice_assign(gradient.deref()),
+            BackwardAction::Increment => self
+                .gradient
+                .borrow_mut()
+                .slice_add_assign(gradient.deref()),
+        }
+
+        if self.counter.recurse_backward() {
+            {
+                let mut operand_gradient = self.operand_gradient.borrow_mut();
+                operand_gradient.slice_assign(self.gradient.borrow().deref());
+
+                l2_normalize_backward(
+                    operand_gradient.deref_mut(),
+                    &self.value.borrow(),
+                    &self.norms.borrow(),
+                    self.axis,
+                );
+            }
+
+            self.operand.backward(&self.operand_gradient.borrow());
+        }
+    }
+
+    fn value(&self) -> Bor<Self::Value> {
+        Bor::RefGuard(self.value.borrow())
+    }
+
+    fn needs_gradient(&self) -> bool {
+        self.needs_gradient
+    }
+
+    fn clear(&self) {
+        if !self.counter.is_zero() {
+            self.operand.clear();
+            self.counter.clear();
+        }
+    }
+}
+
+/// Distance between rows used by `PairwiseDistanceNode`.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum Distance {
+    /// Squared Euclidean distance. Small negative values from rounding
+    /// are clamped to zero, and receive no gradient.
+    SquaredEuclidean,
+    /// One minus the cosine similarity.
+    Cosine,
+}
+
+/// Distances between every row of the LHS and every row of the RHS,
+/// giving an `(lhs rows, rhs rows)` matrix.
+#[derive(Debug)]
+pub struct PairwiseDistanceNode<LHS, RHS> {
+    distance: Distance,
+    value: RefCell<Arr>,
+    gradient: RefCell<Arr>,
+    lhs_gradient: RefCell<Arr>,
+    rhs_gradient: RefCell<Arr>,
+    lhs_normalized: RefCell<Arr>,
+    rhs_normalized: RefCell<Arr>,
+    lhs_norms: RefCell<Arr>,
+    rhs_norms: RefCell<Arr>,
+    lhs: Rc<LHS>,
+    rhs: Rc<RHS>,
+    needs_gradient: bool,
+    counter: PassCounter,
+}
+
+impl<LHS, RHS> PairwiseDistanceNode<LHS, RHS>
+where
+    LHS: Node<Value = Arr>,
+    RHS: Node<Value = Arr>,
+{
+    pub fn new(lhs: Rc<LHS>, rhs: Rc<RHS>, distance: Distance) -> Self {
+        let (lhs_rows, rhs_rows) = {
+            let lhs_value = lhs.value();
+            let rhs_value = rhs.value();
+
+            assert_eq!(
+                lhs_value.cols(),
+                rhs_value.cols(),
+                "LHS and RHS must have the same number of columns."
+            );
+
+            (lhs_value.rows(), rhs_value.rows())
+        };
+
+        let needs_gradient = lhs.needs_gradient() || rhs.needs_gradient();
+        let lhs_gradient = lhs.value().deref() * 0.0;
+        let rhs_gradient = rhs.value().deref() * 0.0;
+
+        let node = PairwiseDistanceNode {
+            distance: distance,
+            value: RefCell::new(Arr::zeros((lhs_rows, rhs_rows))),
+            gradient: RefCell::new(Arr::zeros((lhs_rows, rhs_rows))),
+            lhs_normalized: RefCell::new(lhs_gradient.clone()),
+            rhs_normalized: RefCell::new(rhs_gradient.clone()),
+            lhs_gradient: RefCell::new(lhs_gradient),
+            rhs_gradient: RefCell::new(rhs_gradient),
+            lhs_norms: RefCell::new(Arr::zeros((lhs_rows, 1))),
+            rhs_norms: RefCell::new(Arr::zeros((rhs_rows, 1))),
+            lhs: lhs,
+            rhs: rhs,
+            needs_gradient: needs_gradient,
+            counter: PassCounter::default(),
+        };
+
+        node.compute();
+
+        node
+    }
+
+    fn compute(&self) {
+        let lhs_value = self.lhs.value();
+        let rhs_value = self.rhs.value();
+        let mut value = self.value.borrow_mut();
+
+        match self.distance {
+            Distance::SquaredEuclidean => {
+                let mut lhs_norms = self.lhs_norms.borrow_mut();
+                let mut rhs_norms = self.rhs_norms.borrow_mut();
+
+                for (norm, row) in lhs_norms.iter_mut().zip(lhs_value.genrows()) {
+                    *norm = lane_dot(row.view(), row.view());
+                }
+                for (norm, row) in rhs_norms.iter_mut().zip(rhs_value.genrows()) {
+                    *norm = lane_dot(row.view(), row.view());
+                }
+
+                numerics::mat_mul(
+                    -2.0,
+                    lhs_value.deref(),
+                    &rhs_value.t(),
+                    0.0,
+                    value.deref_mut(),
+                );
+
+                for (mut row, &lhs_norm) in izip!(value.genrows_mut(), lhs_norms.iter()) {
+                    for (distance, &rhs_norm) in row.iter_mut().zip(rhs_norms.iter()) {
+                        *distance = (*distance + lhs_norm + rhs_norm).max(0.0);
+                    }
+                }
+            }
+            Distance::Cosine => {
+                let mut lhs_normalized = self.lhs_normalized.borrow_mut();
+                let mut rhs_normalized = self.rhs_normalized.borrow_mut();
+
+                l2_normalize_lanes(
+                    lhs_normalized.deref_mut(),
+                    self.lhs_norms.borrow_mut().deref_mut(),
+                    lhs_value.deref(),
+                    Axis(1),
+                );
+                l2_normalize_lanes(
+                    rhs_normalized.deref_mut(),
+                    self.rhs_norms.borrow_mut().deref_mut(),
+                    rhs_value.deref(),
+                    Axis(1),
+                );
+
+                numerics::mat_mul(
+                    -1.0,
+                    lhs_normalized.deref(),
+                    &rhs_normalized.t(),
+                    0.0,
+                    value.deref_mut(),
+                );
+
+                value.map_inplace(|x| *x += 1.0);
+            }
+        }
+    }
+}
+
+impl<LHS, RHS> Node for PairwiseDistanceNode<LHS, RHS>
+where
+    LHS: Node<Value = Arr, InputGradient = Arr>,
+    RHS: Node<Value = Arr, InputGradient = Arr>,
+{
+    type Value = Arr;
+    type InputGradient = Arr;
+    fn forward(&self) {
+        if self.counter.forward() == ForwardAction::Cached {
+            return;
+        }
+
+        self.lhs.forward();
+        self.rhs.forward();
+
+        self.compute();
+    }
+    fn backward(&self, gradient: &Ref<Self::InputGradient>) {
+        match self.counter.backward() {
+            BackwardAction::Set => self.gradient.borrow_mut().slice_assign(gradient.deref()),
+            BackwardAction::Increment => self
+                .gradient
+                .borrow_mut()
+                .slice_add_assign(gradient.deref()),
+        }
+
+        if self.counter.recurse_backward() {
+            if self.distance == Distance::SquaredEuclidean {
+                // Distances clamped to zero do not depend on the inputs.
+                for (gradient, &distance) in izip!(
+                    self.gradient.borrow_mut().iter_mut(),
+                    self.value.borrow().iter()
+                ) {
+                    if distance == 0.0 {
+                        *gradient = 0.0;
+                    }
+                }
+            }
+
+            {
+                let gradient = self.gradient.borrow();
+                let mut lhs_gradient = self.lhs_gradient.borrow_mut();
+                let mut rhs_gradient = self.rhs_gradient.borrow_mut();
+
+                match self.distance {
+                    Distance::SquaredEuclidean => {
+                        let lhs_value = self.lhs.value();
+                        let rhs_value = self.rhs.value();
+
+                        numerics::mat_mul(
+                            -2.0,
+                            gradient.deref(),
+                            rhs_value.deref(),
+                            0.0,
+                            lhs_gradient.deref_mut(),
+                        );
+                        numerics::mat_mul(
+                            -2.0,
+                            &gradient.t(),
+                            lhs_value.deref(),
+                            0.0,
+                            rhs_gradient.deref_mut(),
+                        );
+
+                        for (mut dest, value, gradient) in izip!(
+                            lhs_gradient.genrows_mut(),
+                            lhs_value.genrows(),
+                            gradient.genrows()
+                        ) {
+                            dest.scaled_add(2.0 * gradient.scalar_sum(), &value);
+                        }
+                        for (mut dest, value, gradient) in izip!(
+                            rhs_gradient.genrows_mut(),
+                            rhs_value.genrows(),
+                            gradient.gencolumns()
+                        ) {
+                            dest.scaled_add(2.0 * gradient.scalar_sum(), &value);
+                        }
+                    }
+                    Distance::Cosine => {
+                        let lhs_normalized = self.lhs_normalized.borrow();
+                        let rhs_normalized = self.rhs_normalized.borrow();
+
+                        numerics::mat_mul(
+                            -1.0,
+                            gradient.deref(),
+                            rhs_normalized.deref(),
+                            0.0,
+                            lhs_gradient.deref_mut(),
+                        );
+                        numerics::mat_mul(
+                            -1.0,
+                            &gradient.t(),
+                            lhs_normalized.deref(),
+                            0.0,
+                            rhs_gradient.deref_mut(),
+                        );
+
+                        l2_normalize_backward(
+                            lhs_gradient.deref_mut(),
+                            &lhs_normalized,
+                            &self.lhs_norms.borrow(),
+                            Axis(1),
+                        );
+                        l2_normalize_backward(
+                            rhs_gradient.deref_mut(),
+                            &rhs_normalized,
+                            &self.rhs_norms.borrow(),
+                            Axis(1),
+                        );
+                    }
+                }
+            }
+
+            self.lhs.backward(&self.lhs_gradient.borrow());
+            self.rhs.backward(&self.rhs_gradient.borrow());
+        }
+    }
+
+    fn value(&self) -> Bor<Self::Value> {
+        Bor::RefGuard(self.value.borrow())
+    }
+
+    fn needs_gradient(&self) -> bool {
+        self.needs_gradient
+    }
+
+    fn clear(&self) {
+        if !self.counter.is_zero() {
+            self.lhs.clear();
+            self.rhs.clear();
+            self.counter.clear();
+        }
+    }
+}
+
+/// Added to the variance in layer normalisation for numerical stability.
+const LAYER_NORM_EPSILON: f32 = 1e-5;
+
+/// Layer normalisation: normalises each row of the operand to zero mean
+/// and unit variance, then applies a learnable gain and bias (both of
+/// shape `(1, columns)`).
+#[derive(Debug)]
+pub struct LayerNormNode<OP> {
+    value: RefCell<Arr>,
+    normalized: RefCell<Arr>,
+    inverse_stds: RefCell<Arr>,
+    gradient: RefCell<Arr>,
+    operand_gradient: RefCell<Arr>,
+    gain_gradient: RefCell<Arr>,
+    bias_gradient: RefCell<Arr>,
+    operand: Rc<OP>,
+    gain: Rc<ParameterNode>,
+    bias: Rc<ParameterNode>,
+    needs_gradient: bool,
+    counter: PassCounter,
+}
+
+impl<OP> LayerNormNode<OP>
+where
+    OP: Node<Value = Arr>,
+{
+    pub fn new(operand: Rc<OP>, gain: Rc<ParameterNode>, bias: Rc<ParameterNode>) -> Self {
+        let value = operand.value().deref() * 0.0;
+        let cols = value.cols();
+
+        assert_eq!(
+            gain.value().dim(),
+            (1, cols),
+            "Gain must have shape (1, columns)."
+        );
+        assert_eq!(
+            bias.value().dim(),
+            (1, cols),
+            "Bias must have shape (1, columns)."
+        );
+
+        let node = LayerNormNode {
+            normalized: RefCell::new(value.clone()),
+            inverse_stds: RefCell::new(Arr::zeros((value.rows(), 1))),
+            gradient: RefCell::new(value.clone()),
+            operand_gradient: RefCell::new(value.clone()),
+            gain_gradient: RefCell::new(Arr::zeros((1, cols))),
+            bias_gradient: RefCell::new(Arr::zeros((1, cols))),
+            value: RefCell::new(value),
+            operand: operand,
+            gain: gain,
+            bias: bias,
+            needs_gradient: true,
+            counter: PassCounter::default(),
+        };
+
+        node.normalize();
+
+        node
+    }
+
+    fn normalize(&self) {
+        let operand_value = self.operand.value();
+        let gain = self.gain.value();
+        let bias = self.bias.value();
+        let mut value = self.value.borrow_mut();
+        let mut normalized = self.normalized.borrow_mut();
+        let mut inverse_stds = self.inverse_stds.borrow_mut();
+
+        for (mut value_row, mut normalized_row, inverse_std, operand_row) in izip!(
+            value.genrows_mut(),
+            normalized.genrows_mut(),
+            inverse_stds.fast_slice_mut(),
+            operand_value.genrows()
+        ) {
+            let operand_row = operand_row.fast_slice();
+            let mean = numerics::simd_sum(operand_row) / operand_row.len() as f32;
+            let variance = operand_row.iter().map(|&x| (x - mean).powi(2)).sum::<f32>()
+                / operand_row.len() as f32;
+
+            *inverse_std = 1.0 / (variance + LAYER_NORM_EPSILON).sqrt();
+
+            for (value, normalized, &x, &gain, &bias) in izip!(
+                value_row.fast_slice_mut(),
+                normalized_row.fast_slice_mut(),
+                operand_row,
+                gain.fast_slice(),
+                bias.fast_slice()
+            ) {
+                *normalized = (x - mean) * *inverse_std;
+                *value = gain * *normalized + bias;
+            }
+        }
+    }
+}
+
+impl<OP> Node for LayerNormNode<OP>
+where
+    OP: Node<Value = Arr, InputGradient = Arr>,
+{
+    type Value = Arr;
+    type InputGradient = Arr;
+    fn forward(&self) {
+        if self.counter.forward() == ForwardAction::Cached {
+            return;
+        }
+
+        self.operand.forward();
+        self.normalize();
+    }
+    fn backward(&self, gradient: &Ref<Self::InputGradient>) {
+        match self.counter.backward() {
+            BackwardAction::Set => self.gradient.borrow_mut().slice_assign(gradient.deref()),
+            BackwardAction::Increment => self
+                .gradient
+                .borrow_mut()
+                .slice_add_assign(gradient.deref()),
+        }
+
+        if self.counter.recurse_backward() {
+            {
+                let gradient = self.gradient.borrow();
+                let normalized = self.normalized.borrow();
+                let inverse_stds = self.inverse_stds.borrow();
+                let gain = self.gain.value();
+
+                let mut operand_gradient = self.operand_gradient.borrow_mut();
+                let mut gain_gradient = self.gain_gradient.borrow_mut();
+                let mut bias_gradient = self.bias_gradient.borrow_mut();
+
+                gain_gradient.fill(0.0);
+                bias_gradient.fill(0.0);
+
+                for (mut operand_row, gradient_row, normalized_row, &inverse_std) in izip!(
+                    operand_gradient.genrows_mut(),
+                    gradient.genrows(),
+                    normalized.genrows(),
+                    inverse_stds.fast_slice()
+                ) {
+                    let gradient_row = gradient_row.fast_slice();
+                    let normalized_row = normalized_row.fast_slice();
+                    let operand_row = operand_row.fast_slice_mut();
+                    let len = operand_row.len() as f32;
+
+                    // Gradient with respect to the normalised row.
+                    for (dest, &grad, &gain) in
+                        izip!(operand_row.iter_mut(), gradient_row, gain.fast_slice())
+                    {
+                        *dest = grad * gain;
+                    }
+
+                    let mean_gradient = numerics::simd_sum(operand_row) / len;
+                    let mean_projection = numerics::simd_dot(operand_row, normalized_row) / len;
+
+                    for (dest, &normalized) in operand_row.iter_mut().zip(normalized_row) {
+                        *dest =
+                            inverse_std * (*dest - mean_gradient - normalized * mean_projection);
+                    }
+
+                    for (gain_grad, bias_grad, &grad, &normalized) in izip!(
+                        gain_gradient.fast_slice_mut(),
+                        bias_gradient.fast_slice_mut(),
+                        gradient_row,
+                        normalized_row
+                    ) {
+                        *gain_grad += grad * normalized;
+                        *bias_grad += grad;
+                    }
+                }
+            }
+
+            self.operand.backward(&self.operand_gradient.borrow());
+            self.gain.backward(&self.gain_gradient.borrow());
+            self.bias.backward(&self.bias_gradient.borrow());
+        }
+    }
+
+    fn value(&self) -> Bor<Self::Value> {
+        Bor::RefGuard(self.value.borrow())
+    }
+
+    fn needs_gradient(&self) -> bool {
+        self.needs_gradient
+    }
+
+    fn clear(&self) {
+        if !self.counter.is_zero() {
+            self.operand.clear();
+            self.counter.clear();
+        }
+    }
+}
+
+/// Batch normalisation: normalises each column of the operand to zero mean
+/// and unit variance, then applies a learnable gain and bias (both of shape
+/// `(1, columns)`).
+///
+/// In training mode, the statistics of the current batch are used and,
+/// on each forward pass, folded into the running mean and variance,
+/// weighted by `momentum`.
+/// Outside of training mode, the running statistics are used instead.
+#[derive(Debug)]
+pub struct BatchNormNode<OP> {
+    value: RefCell<Arr>,
+    normalized: RefCell<Arr>,
+    inverse_stds: RefCell<Arr>,
+    batch_statistics: Cell<bool>,
+    gradient: RefCell<Arr>,
+    operand_gradient: RefCell<Arr>,
+    gain_gradient: RefCell<Arr>,
+    bias_gradient: RefCell<Arr>,
+    operand: Rc<OP>,
+    gain: Rc<ParameterNode>,
+    bias: Rc<ParameterNode>,
+    running_mean: Arc<HogwildParameter>,
+    running_variance: Arc<HogwildParameter>,
+    momentum: f32,
+    counter: PassCounter,
+}
+
+impl<OP> BatchNormNode<OP>
+where
+    OP: Node<Value = Arr>,
+{
+    pub fn new(
+        operand: Rc<OP>,
+        gain: Rc<ParameterNode>,
+        bias: Rc<ParameterNode>,
+        running_mean: Arc<HogwildParameter>,
+        running_variance: Arc<HogwildParameter>,
+        momentum: f32,
+    ) -> Self {
+        let value = operand.value().deref() * 0.0;
+        let cols = value.cols();
+
+        assert_eq!(
+            gain.value().dim(),
+            (1, cols),
+            "Gain must have shape (1, columns)."
+        );
+        assert_eq!(
+            bias.value().dim(),
+            (1, cols),
+            "Bias must have shape (1, columns)."
+        );
+        assert_eq!(
+            running_mean.value().dim(),
+            (1, cols),
+            "Running mean must have shape (1, columns)."
+        );
+        assert_eq!(
+            running_variance.value().dim(),
+            (1, cols),
+            "Running variance must have shape (1, columns)."
+        );
+
+        let node = BatchNormNode {
+            normalized: RefCell::new(value.clone()),
+            inverse_stds: RefCell::new(Arr::zeros((1, cols))),
+            batch_statistics: Cell::new(false),
+            gradient: RefCell::new(value.clone()),
+            operand_gradient: RefCell::new(value.clone()),
+            gain_gradient: RefCell::new(Arr::zeros((1, cols))),
+            bias_gradient: RefCell::new(Arr::zeros((1, cols))),
+            value: RefCell::new(value),
+            operand: operand,
+            gain: gain,
+            bias: bias,
+            running_mean: running_mean,
+            running_variance: running_variance,
+            momentum: momentum,
+            counter: PassCounter::default(),
+        };
+
+        node.normalize();
+
+        node
+    }
+
+    /// Normalise the operand, returning the batch means and variances
+    /// if they were used.
+    fn normalize(&self) -> Option<(Arr, Arr)> {
+        let operand_value = self.operand.value();
+        let mut inverse_stds = self.inverse_stds.borrow_mut();
+        let mut means = Arr::zeros(inverse_stds.dim());
+
+        let batch_statistics = is_training();
+        self.batch_statistics.set(batch_statistics);
+
+        let statistics = if batch_statistics {
+            let rows = operand_value.rows() as f32;
+            let variances = inverse_stds.deref_mut();
+
+            sum_axis(&mut means, operand_value.deref(), Axis(0), 1.0 / rows);
+            variances.fill(0.0);
+
+            for row in operand_value.genrows() {
+                for (variance, &mean, &x) in izip!(
+                    variances.fast_slice_mut(),
+                    means.fast_slice(),
+                    row.fast_slice()
+                ) {
+                    *variance += (x - mean).powi(2) / rows;
+                }
+            }
+
+            Some((means.clone(), variances.clone()))
+        } else {
+            means.slice_assign(self.running_mean.value());
+            inverse_stds.slice_assign(self.running_variance.value());
+
+            None
+        };
+
+        inverse_stds.map_inplace(|x| *x = 1.0 / (*x + LAYER_NORM_EPSILON).sqrt());
+
+        let gain = self.gain.value();
+        let bias = self.bias.value();
+
+        for (mut value_row, mut normalized_row, operand_row) in izip!(
+            self.value.borrow_mut().genrows_mut(),
+            self.normalized.borrow_mut().genrows_mut(),
+            operand_value.genrows()
+        ) {
+            for (value, normalized, &x, &mean, &inverse_std, &gain, &bias) in izip!(
+                value_row.fast_slice_mut(),
+                normalized_row.fast_slice_mut(),
+                operand_row.fast_slice(),
+                means.fast_slice(),
+                inverse_stds.fast_slice(),
+                gain.fast_slice(),
+                bias.fast_slice()
+            ) {
+                *normalized = (x - mean) * inverse_std;
+                *value = gain * *normalized + bias;
+            }
+        }
+
+        statistics
+    }
+
+    /// Fold the statistics of a batch into the running mean and variance.
+    fn update_running_statistics(&self, means: &Arr, variances: &Arr, rows: usize) {
+        // Racy updates of the shared statistics, in the
+        // same spirit as Hogwild parameter updates.
+        let (running_mean, running_variance) = unsafe {
+            (
+                self.running_mean.value_mut(),
+                self.running_variance.value_mut(),
+            )
+        };
+        let rows = rows as f32;
+        let correction = if rows > 1.0 { rows / (rows - 1.0) } else { 1.0 };
+
+        for (running_mean, running_variance, &mean, &variance) in izip!(
+            running_mean.fast_slice_mut(),
+            running_variance.fast_slice_mut(),
+            means.fast_slice(),
+            variances.fast_slice()
+        ) {
+            *running_mean += self.momentum * (mean - *running_mean);
+            *running_variance += self.momentum * (correction * variance - *running_variance);
+        }
+    }
+}
+
+impl<OP> Node for BatchNormNode<OP>
+where
+    OP: Node<Value = Arr, InputGradient = Arr>,
+{
+    type Value = Arr;
+    type InputGradient = Arr;
+    fn forward(&self) {
+        if self.counter.forward() == ForwardAction::Cached {
+            return;
+        }
+
+        self.operand.forward();
+
+        if let Some((means, variances)) = self.normalize() {
+            self.update_running_statistics(&means, &variances, self.operand.value().rows());
+        }
+    }
+    fn backward(&self, gradient: &Ref<Self::InputGradient>) {
+        match self.counter.backward() {
+            BackwardAction::Set => self.gradient.borrow_mut().slice_assign(gradient.deref()),
+            BackwardAction::Increment => self
+                .gradient
+                .borrow_mut()
+                .slice_add_assign(gradient.deref()),
+        }
+
+        if self.counter.recurse_backward() {
+            {
+                let gradient = self.gradient.borrow();
+                let normalized = self.normalized.borrow();
+                let inverse_stds = self.inverse_stds.borrow();
+                let gain = self.gain.value();
+
+                let mut operand_gradient = self.operand_gradient.borrow_mut();
+                let mut gain_gradient = self.gain_gradient.borrow_mut();
+                let mut bias_gradient = self.bias_gradient.borrow_mut();
+
+                gain_gradient.fill(0.0);
+                bias_gradient.fill(0.0);
+
+                for (gradient_row, normalized_row) in
+                    izip!(gradient.genrows(), normalized.genrows())
+                {
+                    for (gain_grad, bias_grad, &grad, &normalized) in izip!(
+                        gain_gradient.fast_slice_mut(),
+                        bias_gradient.fast_slice_mut(),
+                        gradient_row.fast_slice(),
+                        normalized_row.fast_slice()
+                    ) {
+                        *gain_grad += grad * normalized;
+                        *bias_grad += grad;
+                    }
+                }
+
+                // With batch statistics, the means and variances also depend on
+                // the operand. The column sums of the gradient with respect to the
+                // normalised values, and of its product with them, are simply
+                // the bias and gain gradients scaled by the gain.
+                let rows = gradient.rows() as f32;
+                let batch_statistics = self.batch_statistics.get();
+
+                let gain = gain.fast_slice();
+                let inverse_stds = inverse_stds.fast_slice();
+                let gain_gradient = gain_gradient.fast_slice();
+                let bias_gradient = bias_gradient.fast_slice();
+
+                for (mut operand_row, gradient_row, normalized_row) in izip!(
+                    operand_gradient.genrows_mut(),
+                    gradient.genrows(),
+                    normalized.genrows()
+                ) {
+                    for (col, (dest, &grad, &normalized)) in izip!(
+                        operand_row.fast_slice_mut(),
+                        gradient_row.fast_slice(),
+                        normalized_row.fast_slice()
+                    )
+                    .enumerate()
+                    {
+                        let scale = gain[col] * inverse_stds[col];
+
+                        *dest = if batch_statistics {
+                            let correction = bias_gradient[col] + normalized * gain_gradient[col];
+                            scale * (grad - correction / rows)
+                        } else {
+                            scale * grad
+                        };
+                    }
+                }
+            }
+
+            self.operand.backward(&self.operand_gradient.borrow());
+            self.gain.backward(&self.gain_gradient.borrow());
+            self.bias.backward(&self.bias_gradient.borrow());
+        }
+    }
+
+    fn value(&self) -> Bor<Self::Value> {
+        Bor::RefGuard(self.value.borrow())
+    }
+
+    fn needs_gradient(&self) -> bool {
+        true
+    }
+
+    fn clear(&self) {
+        if !self.counter.is_zero() {
+            self.operand.clear();
+            self.counter.clear();
+        }
+    }
+}
+
+/// Compute the softmax of each row of `source` into `dest`.
+fn row_wise_softmax(dest: &mut Arr, source: &Arr) {
+    for (mut dest_row, source_row) in dest.genrows_mut().into_iter().zip(source.genrows()) {
+        let dest_row = dest_row.fast_slice_mut();
+        let source_row = source_row.fast_slice();
+
+        let max = source_row.iter().fold(std::f32::MIN, |x, y| x.max(*y));
+
+        for (dest, &x) in dest_row.iter_mut().zip(source_row.iter()) {
+            *dest = numerics::exp(x - max);
+        }
+
+        let denominator = numerics::simd_sum(dest_row);
+        dest_row.iter_mut().for_each(|x| *x /= denominator);
+    }
+}
+
+/// Compute the log-softmax of each row of `source` into `dest`.
+fn row_wise_log_softmax(dest: &mut Arr, source: &Arr) {
+    for (mut dest_row, source_row) in dest.genrows_mut().into_iter().zip(source.genrows()) {
+        let dest_row = dest_row.fast_slice_mut();
+        let source_row = source_row.fast_slice();
+
+        let max = source_row.iter().fold(std::f32::MIN, |x, y| x.max(*y));
+        let denominator = max + numerics::softmax_exp_sum(source_row, max).ln();
+
+        for (dest, &x) in dest_row.iter_mut().zip(source_row.iter()) {
+            *dest = x - denominator;
+        }
+    }
+}
+
+#[derive(Debug)]
+pub struct SoftmaxNode<OP> {
+    value: RefCell<Arr>,
+    operand_gradient: RefCell<Arr>,
+    operand: Rc<OP>,
+    needs_gradient: bool,
+    counter: PassCounter,
+}
+
+impl<OP> SoftmaxNode<OP>
+where
+    OP: Node<Value = Arr>,
+{
+    pub fn new(operand: Rc<OP>) -> Self {
+        let mut value = operand.value().deref() * 0.0;
+        row_wise_softmax(&mut value, operand.value().deref());
+
+        let gradient = &value * 0.0;
+        let needs_gradient = operand.needs_gradient();
+
+        SoftmaxNode {
+            value: RefCell::new(value),
+            operand_gradient: RefCell::new(gradient),
+            operand: operand,
+            needs_gradient: needs_gradient,
+            counter: PassCounter::default(),
+        }
+    }
+}
+
+impl<OP> Node for SoftmaxNode<OP>
+where
+    OP: Node<Value = Arr, InputGradient = Arr>,
+{
+    type Value = Arr;
+    type InputGradient = Arr;
+    fn forward(&self) {
+        if self.counter.forward() == ForwardAction::Cached {
+            return;
+        }
+
+        self.operand.forward();
+        row_wise_softmax(
+            self.value.borrow_mut().deref_mut(),
+            self.operand.value().deref(),
+        );
+    }
+    fn backward(&self, gradient: &Ref<Self::InputGradient>) {
+        let beta = match self.counter.backward() {
+            BackwardAction::Set => 0.0,
+            BackwardAction::Increment => 1.0,
+        };
+
+        {
+            let value = self.value.borrow();
+            let mut operand_gradient = self.operand_gradient.borrow_mut();
+
+            // The vector-jacobian product for each row is
+            // softmax * (gradient - <gradient, softmax>).
+            for (mut out_row, value_row, grad_row) in izip!(
+                operand_gradient.genrows_mut(),
+                value.genrows(),
+                gradient.genrows()
+            ) {
+                let value_row = value_row.fast_slice();
+                let grad_row = grad_row.fast_slice();
+                let dot = numerics::simd_dot(value_row, grad_row);
+
+                for (out_grad, &val, &grad) in izip!(out_row.fast_slice_mut(), value_row, grad_row)
+                {
+                    *out_grad = beta * *out_grad + val * (grad - dot);
+                }
+            }
+        }
+
+        if self.counter.recurse_backward() {
+            self.operand.backward(&self.operand_gradient.borrow());
+        }
+    }
+    fn value(&self) -> Bor<Self::Value> {
+        Bor::RefGuard(self.value.borrow())
+    }
+    fn needs_gradient(&self) -> bool {
+        self.needs_gradient
+    }
+    fn clear(&self) {
+        if !self.counter.is_zero() {
+            self.operand.clear();
+            self.counter.clear();
+        }
+    }
+}
+
+#[derive(Debug)]
+pub struct LogSoftmaxNode<OP> {
+    value: RefCell<Arr>,
+    operand_gradient: RefCell<Arr>,
+    operand: Rc<OP>,
+    needs_gradient: bool,
+    counter: PassCounter,
+}
+
+impl<OP> LogSoftmaxNode<OP>
+where
+    OP: Node<Value = Arr>,
+{
+    pub fn new(operand: Rc<OP>) -> Self {
+        let mut value = operand.value().deref() * 0.0;
+        row_wise_log_softmax(&mut value, operand.value().deref());
+
+        let gradient = &value * 0.0;
+        let needs_gradient = operand.needs_gradient();
+
+        LogSoftmaxNode {
+            value: RefCell::new(value),
+            operand_gradient: RefCell::new(gradient),
+            operand: operand,
+            needs_gradient: needs_gradient,
+            counter: PassCounter::default(),
+        }
+    }
+
+    /// An additional method for zeroing the counter for use in the
+    /// log-softmax loss, where the actuall log-softmax layer is skipped
+    /// when backpropagating.
+    pub fn zero_counter(&self) {
+        self.counter.clear();
+    }
+}
+
+impl<OP> Node for LogSoftmaxNode<OP>
+where
+    OP: Node<Value = Arr, InputGradient = Arr>,
+{
+    type Value = Arr;
+    type InputGradient = Arr;
+    fn forward(&self) {
+        if self.counter.forward() == ForwardAction::Cached {
+            return;
+        }
+
+        self.operand.forward();
+        row_wise_log_softmax(
+            self.value.borrow_mut().deref_mut(),
+            self.operand.value().deref(),
+        );
+    }
+    fn backward(&self, gradient: &Ref<Self::InputGradient>) {
+        let beta = match self.counter.backward() {
+            BackwardAction::Set => 0.0,
+            BackwardAction::Increment => 1.0,
+        };
+
+        {
+            let value = self.value.borrow();
+            let mut downstream_gradient = self.operand_gradient.borrow_mut();
+
+            for (mut out_row, value_row, grad_row) in izip!(
+                downstream_gradient.genrows_mut(),
+                value.genrows(),
+                gradient.genrows()
+            ) {
+                let grad_row = grad_row.fast_slice();
+                let gradient_sum = numerics::simd_sum(grad_row);
+
+                for (out_grad, in_grad, &val) in
+                    izip!(out_row.fast_slice_mut(), grad_row, value_row.fast_slice())
+                {
+                    *out_grad = beta * *out_grad + in_grad - numerics::exp(val) * gradient_sum;
+                }
+            }
+        }
+
+        if self.counter.recurse_backward() {
+            self.operand.backward(&self.operand_gradient.borrow());
+        }
+    }
+    fn value(&self) -> Bor<Self::Value> {
+        Bor::RefGuard(self.value.borrow())
+    }
+    fn needs_gradient(&self) -> bool {
+        self.needs_gradient
+    }
+    fn clear(&self) {
+        if !self.counter.is_zero() {
+            self.operand.clear();
+            self.counter.clear();
+        }
+    }
+}
+
+#[derive(Debug)]
+pub struct SumNode<OP> {
+    value: RefCell<Arr>,
+    operand_gradient: RefCell<Arr>,
+    operand: Rc<OP>,
+    scale: f32,
+    needs_gradient: bool,
+    counter: PassCounter,
+}
+
+impl<OP> SumNode<OP>
+where
+    OP: Node<Value = Arr>,
+{
+    pub fn new(operand: Rc<OP>) -> Self {
+        SumNode::with_scale(operand, 1.0)
+    }
+
+    /// Mean of all the elements: the sum scaled by the
+    /// reciprocal of the number of elements.
+    pub fn new_mean(operand: Rc<OP>) -> Self {
+        let scale = 1.0 / operand.value().len() as f32;
+        SumNode::with_scale(operand, scale)
+    }
+
+    fn with_scale(operand: Rc<OP>, scale: f32) -> Self {
+        let value = {
+            let mut value = Arr::zeros((1, 1));
+            value.fill(scale * operand.value().scalar_sum());
+            value
+        };
+
+        let gradient = operand.value().deref() * 0.0;
+        let needs_gradient = operand.needs_gradient();
+
+        SumNode {
+            value: RefCell::new(value),
+            operand_gradient: RefCell::new(gradient),
+            operand: operand,
+            scale: scale,
+            needs_gradient: needs_gradient,
+            counter: PassCounter::default(),
+        }
+    }
+}
+
+impl<OP> Node for SumNode<OP>
+where
+    OP: Node<Value = Arr, InputGradient = Arr>,
+{
+    type Value = Arr;
+    type InputGradient = Arr;
+    fn forward(&self) {
+        if self.counter.forward() == ForwardAction::Cached {
+            return;
+        }
+
+        self.operand.forward();
+
+        let mut dest = self.value.borrow_mut();
+        dest[(0, 0)] = self.scale * self.operand.value().scalar_sum();
+    }
+    fn backward(&self, gradient: &Ref<Self::InputGradient>) {
+        debug_assert!(gradient.len() == 1, "Input gradient must be a scalar.");
+
+        match self.counter.backward() {
+            BackwardAction::Set => {
+                self.operand_gradient
+                    .borrow_mut()
+                    .fill(self.scale * gradient[(0, 0)]);
+            }
+            BackwardAction::Increment => {
+                self.operand_gradient
+                    .borrow_mut()
+                    .slice_add_assign(self.scale * gradient[(0, 0)]);
+            }
+        }
+
+        if self.counter.recurse_backward() {
+            self.operand.backward(&self.operand_gradient.borrow());
+        }
+    }
+    fn value(&self) -> Bor<Self::Value> {
+        Bor::RefGuard(self.value.borrow())
+    }
+    fn needs_gradient(&self) -> bool {
+        self.needs_gradient
+    }
+
+    fn clear(&self) {
+        if !self.counter.is_zero() {
+            self.operand.clear();
+            self.counter.clear();
+        }
+    }
+}
+
+#[derive(Debug)]
+pub struct SumAxisNode<OP> {
+    axis: ndarray::Axis,
+    value: RefCell<Arr>,
+    operand_gradient: RefCell<Arr>,
+    operand: Rc<OP>,
+    scale: f32,
+    needs_gradient: bool,
+    counter: PassCounter,
+}
+
+impl<OP> SumAxisNode<OP>
+where
+    OP: Node<Value = Arr>,
+{
+    pub fn new(operand: Rc<OP>, axis: ndarray::Axis) -> Self {
+        SumAxisNode::with_scale(operand, axis, 1.0)
+    }
+
+    /// Mean along an axis: the sum scaled by the reciprocal
+    /// of the length of that axis.
+    pub fn new_mean(operand: Rc<OP>, axis: ndarray::Axis) -> Self {
+        let scale = 1.0 / operand.value().len_of(axis) as f32;
+        SumAxisNode::with_scale(operand, axis, scale)
+    }
+
+    fn with_scale(operand: Rc<OP>, axis: ndarray::Axis, scale: f32) -> Self {
+        let value = {
+            let operand_value = operand.value();
+            let mut value = match axis {
+                ndarray::Axis(0) => Arr::zeros((1, operand_value.cols())),
+                ndarray::Axis(1) => Arr::zeros((operand_value.rows(), 1)),
+                _ => panic!("Reducing tensors not allowed."),
+            };
+            sum_axis(&mut value, operand_value.deref(), axis, scale);
+            value
+        };
+
+        let gradient = operand.value().deref() * 0.0;
+        let needs_gradient = operand.needs_gradient();
+
+        SumAxisNode {
+            axis: axis,
+            value: RefCell::new(value),
+            operand_gradient: RefCell::new(gradient),
+            operand: operand,
+            scale: scale,
+            needs_gradient: needs_gradient,
+            counter: PassCounter::default(),
+        }
+    }
+}
+
+/// Sum `source` along `axis` into `dest`, scaling by `scale`.
+fn sum_axis(dest: &mut Arr, source: &Arr, axis: ndarray::Axis, scale: f32) {
+    match axis {
+        ndarray::Axis(0) => {
+            let dest_slice = dest.fast_slice_mut();
+
+            for (idx, row) in source.genrows().into_iter().enumerate() {
+                if idx == 0 {
+                    numerics::simd_scaled_assign(dest_slice, row.fast_slice(), scale);
+                } else {
+                    numerics::simd_scaled_add(dest_slice, row.fast_slice(), scale);
+                }
+            }
+        }
+        ndarray::Axis(1) => {
+            for (result, row) in dest.fast_slice_mut().iter_mut().zip(source.genrows()) {
+                *result = scale * numerics::simd_sum(row.fast_slice());
+            }
+        }
+        _ => panic!("Reducing tensors not allowed."),
+    }
+}
+
+impl<OP> Node for SumAxisNode<OP>
+where
+    OP: Node<Value = Arr, InputGradient = Arr>,
+{
+    type Value = Arr;
+    type InputGradient = Arr;
+    fn forward(&self) {
+        if self.counter.forward() == ForwardAction::Cached {
+            return;
+        }
+
+        self.operand.forward();
+
+        sum_axis(
+            self.value.borrow_mut().deref_mut(),
+            self.operand.value().deref(),
+            self.axis,
+            self.scale,
+        );
+    }
+    fn backward(&self, gradient: &Ref<Self::InputGradient>) {
+        {
+            let mut operand_gradient = self.operand_gradient.borrow_mut();
+            let action = self.counter.backward();
+
+            match self.axis {
+                ndarray::Axis(0) => {
+                    let gradient_slice = gradient.fast_slice();
+
+                    for mut row in operand_gradient.genrows_mut() {
+                        match action {
+                            BackwardAction::Set => numerics::simd_scaled_assign(
+                                row.fast_slice_mut(),
+                                gradient_slice,
+                                self.scale,
+                            ),
+                            BackwardAction::Increment => numerics::simd_scaled_add(
+                                row.fast_slice_mut(),
+                                gradient_slice,
+                                self.scale,
+                            ),
+                        }
+                    }
+                }
+                ndarray::Axis(1) => {
+                    for (mut row, &grad) in operand_gradient
+                        .genrows_mut()
+                        .into_iter()
+                        .zip(gradient.fast_slice())
+                    {
+                        let grad = self.scale * grad;
+
+                        match action {
+                            BackwardAction::Set => row.fill(grad),
+                            BackwardAction::Increment => {
+                                row.fast_slice_mut().iter_mut().for_each(|x| *x += grad)
+                            }
+                        }
+                    }
+                }
+                _ => panic!("Reducing tensors not allowed."),
+            }
+        }
+
+        if self.counter.recurse_backward() {
+            self.operand.backward(&self.operand_gradient.borrow());
+        }
+    }
+    fn value(&self) -> Bor<Self::Value> {
+        Bor::RefGuard(self.value.borrow())
+    }
+    fn needs_gradient(&self) -> bool {
+        self.needs_gradient
+    }
+
+    fn clear(&self) {
+        if !self.counter.is_zero() {
+            self.operand.clear();
+            self.counter.clear();
+        }
+    }
+}
+
+/// Numerically stable log-sum-exp of a lane.
+fn logsumexp(lane: ndarray::ArrayView1<f32>) -> f32 {
+    let max = lane
+        .iter()
+        .fold(std::f32::NEG_INFINITY, |acc, &x| acc.max(x));
+
+    if max == std::f32::NEG_INFINITY {
+        return max;
+    }
+
+    let sum = match lane.as_slice() {
+        Some(slice) => numerics::softmax_exp_sum(slice, max),
+        None => lane.iter().map(|&x| numerics::exp(x - max)).sum(),
+    };
+
+    max + numerics::ln(sum)
+}
+
+/// Log-sum-exp along an axis. The reduced axis is kept with length 1.
+#[derive(Debug)]
+pub struct LogSumExpNode<OP> {
+    axis: ndarray::Axis,
+    value: RefCell<Arr>,
+    operand_gradient: RefCell<Arr>,
+    operand: Rc<OP>,
+    needs_gradient: bool,
+    counter: PassCounter,
+}
+
+impl<OP> LogSumExpNode<OP>
+where
+    OP: Node<Value = Arr>,
+{
+    pub fn new(operand: Rc<OP>, axis: ndarray::Axis) -> Self {
         let value = {
             let operand_value = operand.value();
-            let operand_slice = operand_value.deref().as_slice().unwrap();
-            let max = operand_slice.iter().fold(std::f32::MIN, |x, y| x.max(*y));
+            let mut value = match axis {
+                ndarray::Axis(0) => Arr::zeros((1, operand_value.cols())),
+                ndarray::Axis(1) => Arr::zeros((operand_value.rows(), 1)),
+                _ => panic!("Reducing tensors not allowed."),
+            };
+
+            for (result, lane) in value.iter_mut().zip(operand_value.lanes(axis)) {
+                *result = logsumexp(lane);
+            }
+
+            value
+        };
+
+        let gradient = Arr::zeros(operand.value().dim());
+        let needs_gradient = operand.needs_gradient();
+
+        LogSumExpNode {
+            axis: axis,
+            value: RefCell::new(value),
+            operand_gradient: RefCell::new(gradient),
+            operand: operand,
+            needs_gradient: needs_gradient,
+            counter: PassCounter::default(),
+        }
+    }
+}
+
+impl<OP> Node for LogSumExpNode<OP>
+where
+    OP: Node<Value = Arr, InputGradient = Arr>,
+{
+    type Value = Arr;
+    type InputGradient = Arr;
+    fn forward(&self) {
+        if self.counter.forward() == ForwardAction::Cached {
+            return;
+        }
+
+        self.operand.forward();
+
+        let operand_value = self.operand.value();
+
+        for (result, lane) in self
+            .value
+            .borrow_mut()
+            .iter_mut()
+            .zip(operand_value.lanes(self.axis))
+        {
+            *result = logsumexp(lane);
+        }
+    }
+    fn backward(&self, gradient: &Ref<Self::InputGradient>) {
+        let beta = match self.counter.backward() {
+            BackwardAction::Set => 0.0,
+            BackwardAction::Increment => 1.0,
+        };
+
+        {
+            let value = self.value.borrow();
+            let operand_value = self.operand.value();
+            let mut operand_gradient = self.operand_gradient.borrow_mut();
+
+            // The gradient is the softmax along the reduced axis.
+            for (mut out_lane, lane, &result, &grad) in izip!(
+                operand_gradient.lanes_mut(self.axis),
+                operand_value.lanes(self.axis),
+                value.iter(),
+                gradient.iter()
+            ) {
+                for (out_grad, &x) in out_lane.iter_mut().zip(lane.iter()) {
+                    let softmax = if result == std::f32::NEG_INFINITY {
+                        0.0
+                    } else {
+                        numerics::exp(x - result)
+                    };
+
+                    *out_grad = beta * *out_grad + grad * softmax;
+                }
+            }
+        }
+
+        if self.counter.recurse_backward() {
+            self.operand.backward(&self.operand_gradient.borrow());
+        }
+    }
+    fn value(&self) -> Bor<Self::Value> {
+        Bor::RefGuard(self.value.borrow())
+    }
+    fn needs_gradient(&self) -> bool {
+        self.needs_gradient
+    }
+
+    fn clear(&self) {
+        if !self.counter.is_zero() {
+            self.operand.clear();
+            self.counter.clear();
+        }
+    }
+}
+
+/// Which extremum an `ExtremumAxisNode` selects.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum Extremum {
+    Max,
+    Min,
+}
+
+impl Extremum {
+    #[inline(always)]
+    fn is_better(self, candidate: f32, current: f32) -> bool {
+        match self {
+            Extremum::Max => candidate > current,
+            Extremum::Min => candidate < current,
+        }
+    }
+}
+
+/// Max or min along an axis. The gradient is routed to the
+/// (first) element that attained the extremum.
+#[derive(Debug)]
+pub struct ExtremumAxisNode<OP> {
+    axis: ndarray::Axis,
+    extremum: Extremum,
+    value: RefCell<Arr>,
+    indices: RefCell<Vec<usize>>,
+    operand_gradient: RefCell<Arr>,
+    operand: Rc<OP>,
+    needs_gradient: bool,
+    counter: PassCounter,
+}
+
+impl<OP> ExtremumAxisNode<OP>
+where
+    OP: Node<Value = Arr>,
+{
+    pub fn new(operand: Rc<OP>, axis: ndarray::Axis, extremum: Extremum) -> Self {
+        let (value, indices) = {
+            let operand_value = operand.value();
+            let mut value = match axis {
+                ndarray::Axis(0) => Arr::zeros((1, operand_value.cols())),
+                ndarray::Axis(1) => Arr::zeros((operand_value.rows(), 1)),
+                _ => panic!("Reducing tensors not allowed."),
+            };
+            let mut indices = vec![0; value.len()];
+
+            extremum_axis(
+                &mut value,
+                &mut indices,
+                operand_value.deref(),
+                axis,
+                extremum,
+            );
+
+            (value, indices)
+        };
+
+        let gradient = operand.value().deref() * 0.0;
+        let needs_gradient = operand.needs_gradient();
+
+        ExtremumAxisNode {
+            axis: axis,
+            extremum: extremum,
+            value: RefCell::new(value),
+            indices: RefCell::new(indices),
+            operand_gradient: RefCell::new(gradient),
+            operand: operand,
+            needs_gradient: needs_gradient,
+            counter: PassCounter::default(),
+        }
+    }
+}
+
+/// Find the extrema of `source` along `axis`, storing them in `dest`
+/// and their positions along `axis` in `indices`.
+fn extremum_axis(
+    dest: &mut Arr,
+    indices: &mut [usize],
+    source: &Arr,
+    axis: ndarray::Axis,
+    extremum: Extremum,
+) {
+    match axis {
+        ndarray::Axis(0) => {
+            let dest_slice = dest.fast_slice_mut();
+
+            for (row_idx, row) in source.genrows().into_iter().enumerate() {
+                for (result, idx, &x) in
+                    izip!(dest_slice.iter_mut(), indices.iter_mut(), row.fast_slice())
+                {
+                    if row_idx == 0 || extremum.is_better(x, *result) {
+                        *result = x;
+                        *idx = row_idx;
+                    }
+                }
+            }
+        }
+        ndarray::Axis(1) => {
+            for (result, idx, row) in izip!(
+                dest.fast_slice_mut().iter_mut(),
+                indices.iter_mut(),
+                source.genrows()
+            ) {
+                let row = row.fast_slice();
+
+                *result = row[0];
+                *idx = 0;
 
-            let denominator = max + operand_slice
+                for (col_idx, &x) in row.iter().enumerate().skip(1) {
+                    if extremum.is_better(x, *result) {
+                        *result = x;
+                        *idx = col_idx;
+                    }
+                }
+            }
+        }
+        _ => panic!("Reducing tensors not allowed."),
+    }
+}
+
+impl<OP> Node for ExtremumAxisNode<OP>
+where
+    OP: Node<Value = Arr, InputGradient = Arr>,
+{
+    type Value = Arr;
+    type InputGradient = Arr;
+    fn forward(&self) {
+        if self.counter.forward() == ForwardAction::Cached {
+            return;
+        }
+
+        self.operand.forward();
+
+        extremum_axis(
+            self.value.borrow_mut().deref_mut(),
+            self.indices.borrow_mut().as_mut_slice(),
+            self.operand.value().deref(),
+            self.axis,
+            self.extremum,
+        );
+    }
+    fn backward(&self, gradient: &Ref<Self::InputGradient>) {
+        {
+            let mut operand_gradient = self.operand_gradient.borrow_mut();
+
+            if self.counter.backward() == BackwardAction::Set {
+                operand_gradient.fill(0.0);
+            }
+
+            for (position, (&idx, &grad)) in self
+                .indices
+                .borrow()
                 .iter()
-                .map(|&x| numerics::exp(x - max))
-                .sum::<f32>()
-                .ln();
+                .zip(gradient.fast_slice())
+                .enumerate()
+            {
+                let operand_idx = match self.axis {
+                    ndarray::Axis(0) => (idx, position),
+                    _ => (position, idx),
+                };
+
+                operand_gradient[operand_idx] += grad;
+            }
+        }
+
+        if self.counter.recurse_backward() {
+            self.operand.backward(&self.operand_gradient.borrow());
+        }
+    }
+    fn value(&self) -> Bor<Self::Value> {
+        Bor::RefGuard(self.value.borrow())
+    }
+    fn needs_gradient(&self) -> bool {
+        self.needs_gradient
+    }
+
+    fn clear(&self) {
+        if !self.counter.is_zero() {
+            self.operand.clear();
+            self.counter.clear();
+        }
+    }
+}
+
+/// An input node for integer indices into `ParameterNode`s, used
+/// for implementing indexable embedding layers.
+#[derive(Debug)]
+pub struct IndexInputNode {
+    pub(crate) value: RefCell<SmallVec<[usize; 4]>>,
+}
+
+impl IndexInputNode {
+    /// Create a new index input node.
+    pub fn new(value: &[usize]) -> Variable<Self> {
+        Variable::new(
+            Rc::new(IndexInputNode {
+                value: RefCell::new(SmallVec::from(value)),
+            }),
+            Vec::new(),
+        )
+    }
+}
+
+impl Node for IndexInputNode {
+    type Value = SmallVec<[usize; 4]>;
+    type InputGradient = Arr;
+    fn forward(&self) {}
+    fn backward(&self, _: &Ref<Self::InputGradient>) {}
+    fn value(&self) -> Bor<Self::Value> {
+        Bor::RefGuard(self.value.borrow())
+    }
+    fn needs_gradient(&self) -> bool {
+        false
+    }
+    fn clear(&self) {}
+}
+
+/// Gathers rows of its operand. When the operand is a `ParameterNode`,
+/// gradients are accumulated sparsely, straight into the parameter;
+/// otherwise, they are scatter-added into a dense operand gradient.
+#[derive(Debug)]
+pub struct IndexNode<OP> {
+    value: RefCell<Arr>,
+    index_value: RefCell<SmallVec<[usize; 4]>>,
+    operand_gradient: RefCell<Arr>,
+    index: Rc<IndexInputNode>,
+    operand: Rc<OP>,
+    needs_gradient: bool,
+    counter: PassCounter,
+}
+
+impl<OP> IndexNode<OP>
+where
+    OP: Node<Value = Arr>,
+{
+    pub fn new(operand: Rc<OP>, index: Rc<IndexInputNode>) -> Self {
+        let value = operand.value().select(Axis(0), &index.value()[..]);
+        let idx_value = index.value().clone();
+        let needs_gradient = operand.needs_gradient();
 
-            operand_value.deref() - denominator
+        // Parameters accumulate their gradients sparsely, so
+        // there is no need for a dense buffer.
+        let grad = if IndexNode::<OP>::parameter(&operand).is_some() {
+            Arr::zeros((0, 0))
+        } else {
+            operand.value().deref() * 0.0
         };
 
-        let gradient = &value * 0.0;
+        IndexNode {
+            value: RefCell::new(value),
+            index_value: RefCell::new(idx_value),
+            operand_gradient: RefCell::new(grad),
+            index: index,
+            operand: operand,
+            needs_gradient: needs_gradient,
+            counter: PassCounter::default(),
+        }
+    }
+
+    fn parameter(operand: &Rc<OP>) -> Option<&ParameterNode> {
+        (operand.deref() as &Any).downcast_ref::<ParameterNode>()
+    }
+}
+
+impl<OP> Node for IndexNode<OP>
+where
+    OP: Node<Value = Arr, InputGradient = Arr>,
+{
+    type Value = Arr;
+    type InputGradient = Arr;
+    fn forward(&self) {
+        if self.counter.forward() == ForwardAction::Cached {
+            return;
+        }
+
+        self.operand.forward();
+
+        let operand_value = self.operand.value();
+
+        let mut idx_value = self.index_value.borrow_mut();
+        idx_value.clear();
+        idx_value.extend_from_slice(&self.index.value()[..]);
+
+        let mut arr_value = self.value.borrow_mut();
+
+        debug_assert_eq!(
+            arr_value.shape()[0],
+            idx_value.len(),
+            "Result of indexing operation must maintain consistent shape between iterations."
+        );
+
+        for (&idx, mut row) in idx_value.iter().zip(arr_value.genrows_mut()) {
+            let new_val = operand_value.subview(Axis(0), idx);
+
+            row.slice_assign(&new_val);
+        }
+    }
+
+    fn backward(&self, gradient: &Ref<Self::InputGradient>) {
+        if let Some(parameter) = IndexNode::parameter(&self.operand) {
+            self.counter.backward();
+            parameter
+                .gradient
+                .borrow_mut()
+                .accumulate_gradient((&self.index_value.borrow()[..], gradient.deref()));
+            self.counter.recurse_backward();
+
+            return;
+        }
+
+        {
+            let mut operand_gradient = self.operand_gradient.borrow_mut();
+
+            if self.counter.backward() == BackwardAction::Set {
+                operand_gradient.fill(0.0);
+            }
+
+            for (&idx, grad_row) in self.index_value.borrow().iter().zip(gradient.genrows()) {
+                operand_gradient
+                    .subview_mut(Axis(0), idx)
+                    .slice_add_assign(&grad_row);
+            }
+        }
+
+        if self.counter.recurse_backward() {
+            self.operand.backward(&self.operand_gradient.borrow());
+        }
+    }
+
+    fn value(&self) -> Bor<Self::Value> {
+        Bor::RefGuard(self.value.borrow())
+    }
+
+    fn needs_gradient(&self) -> bool {
+        self.needs_gradient
+    }
+    fn clear(&self) {
+        if !self.counter.is_zero() {
+            self.operand.clear();
+            self.counter.clear();
+        }
+    }
+}
+
+/// Adds rows of its operand into the output rows given by the index:
+/// the inverse of `IndexNode`. Optionally averages, rather than sums,
+/// the rows that land in the same output row.
+#[derive(Debug)]
+pub struct ScatterAddNode<OP> {
+    mean: bool,
+    value: RefCell<Arr>,
+    index_value: RefCell<SmallVec<[usize; 4]>>,
+    scale: RefCell<Vec<f32>>,
+    operand_gradient: RefCell<Arr>,
+    index: Rc<IndexInputNode>,
+    operand: Rc<OP>,
+    needs_gradient: bool,
+    counter: PassCounter,
+}
+
+impl<OP> ScatterAddNode<OP>
+where
+    OP: Node<Value = Arr>,
+{
+    pub fn new(operand: Rc<OP>, index: Rc<IndexInputNode>, num_rows: usize) -> Self {
+        ScatterAddNode::with_mean(operand, index, num_rows, false)
+    }
+
+    pub fn new_mean(operand: Rc<OP>, index: Rc<IndexInputNode>, num_rows: usize) -> Self {
+        ScatterAddNode::with_mean(operand, index, num_rows, true)
+    }
+
+    fn with_mean(operand: Rc<OP>, index: Rc<IndexInputNode>, num_rows: usize, mean: bool) -> Self {
+        let mut value = Arr::zeros((num_rows, operand.value().cols()));
+        let mut scale = vec![0.0; num_rows];
+        let idx_value = index.value().clone();
+
+        scatter_add(
+            &mut value,
+            &mut scale,
+            operand.value().deref(),
+            &idx_value,
+            mean,
+        );
+
+        let gradient = operand.value().deref() * 0.0;
         let needs_gradient = operand.needs_gradient();
 
-        LogSoftmaxNode {
+        ScatterAddNode {
+            mean: mean,
             value: RefCell::new(value),
+            index_value: RefCell::new(idx_value),
+            scale: RefCell::new(scale),
             operand_gradient: RefCell::new(gradient),
+            index: index,
             operand: operand,
             needs_gradient: needs_gradient,
             counter: PassCounter::default(),
         }
     }
+}
 
-    /// An additional method for zeroing the counter for use in the
-    /// log-softmax loss, where the actuall log-softmax layer is skipped
-    /// when backpropagating.
-    pub fn zero_counter(&self) {
-        self.counter.clear();
+/// Add the rows of `source` into the rows of `dest` given by `index`,
+/// storing the gradient scaling factor of each row of `dest` in `scale`.
+fn scatter_add(dest: &mut Arr, scale: &mut [f32], source: &Arr, index: &[usize], mean: bool) {
+    assert_eq!(
+        source.rows(),
+        index.len(),
+        "Scatter-add needs one index per row: got {} rows and {} indices.",
+        source.rows(),
+        index.len()
+    );
+
+    dest.fill(0.0);
+
+    for x in scale.iter_mut() {
+        *x = 0.0;
+    }
+
+    for (&idx, source_row) in index.iter().zip(source.genrows()) {
+        assert!(
+            idx < dest.rows(),
+            "Index {} out of bounds for {} output rows.",
+            idx,
+            dest.rows()
+        );
+
+        dest.subview_mut(Axis(0), idx).slice_add_assign(&source_row);
+        scale[idx] += 1.0;
+    }
+
+    for (mut dest_row, x) in dest.genrows_mut().into_iter().zip(scale.iter_mut()) {
+        *x = if !mean {
+            1.0
+        } else if *x > 0.0 {
+            1.0 / *x
+        } else {
+            0.0
+        };
+
+        if mean {
+            let x = *x;
+            dest_row.map_inplace(|v| *v *= x);
+        }
     }
 }
 
-impl<OP> Node for LogSoftmaxNode<OP>
+impl<OP> Node for ScatterAddNode<OP>
 where
     OP: Node<Value = Arr, InputGradient = Arr>,
 {
@@ -2398,44 +5718,43 @@ where
         }
 
         self.operand.forward();
-        let mut dest = self.value.borrow_mut();
-        dest.assign(self.operand.value().deref());
-
-        let operand_value = self.operand.value();
-        let operand_slice = operand_value.deref().as_slice().unwrap();
-        let max = operand_slice.iter().fold(std::f32::MIN, |x, y| x.max(*y));
 
-        let denominator = max + numerics::softmax_exp_sum(operand_slice, max).ln();
+        let mut idx_value = self.index_value.borrow_mut();
+        idx_value.clear();
+        idx_value.extend_from_slice(&self.index.value()[..]);
 
-        dest.as_slice_mut()
-            .unwrap()
-            .iter_mut()
-            .for_each(|x| *x -= denominator);
+        scatter_add(
+            &mut self.value.borrow_mut(),
+            &mut self.scale.borrow_mut(),
+            self.operand.value().deref(),
+            &idx_value,
+            self.mean,
+        );
     }
-    fn backward(&self, gradient: &Ref<Self::InputGradient>) {
-        let beta = match self.counter.backward() {
-            BackwardAction::Set => 0.0,
-            BackwardAction::Increment => 1.0,
-        };
 
+    fn backward(&self, gradient: &Ref<Self::InputGradient>) {
         {
-            let value = self.value.borrow();
-            let value_slice = value.as_slice().expect("Can't get value slice.");
+            let mut operand_gradient = self.operand_gradient.borrow_mut();
+            let scale = self.scale.borrow();
 
-            let gradient_slice = gradient
-                .as_slice()
-                .expect("Can't get input gradient slice.");
-            let mut downstream_gradient = self.operand_gradient.borrow_mut();
-            let downstream_gradient_slice = downstream_gradient
-                .as_slice_mut()
-                .expect("Can't get output gradient slice");
-
-            let gradient_sum = numerics::simd_sum(gradient_slice);
+            let beta = match self.counter.backward() {
+                BackwardAction::Set => 0.0,
+                BackwardAction::Increment => 1.0,
+            };
 
-            for (out_grad, in_grad, &val) in
-                izip!(downstream_gradient_slice, gradient_slice, value_slice)
+            for (&idx, mut grad_row) in self
+                .index_value
+                .borrow()
+                .iter()
+                .zip(operand_gradient.genrows_mut())
             {
-                *out_grad = beta * *out_grad + in_grad - numerics::exp(val) * gradient_sum;
+                for (dest, &grad) in grad_row
+                    .fast_slice_mut()
+                    .iter_mut()
+                    .zip(gradient.subview(Axis(0), idx).fast_slice())
+                {
+                    *dest = beta * *dest + scale[idx] * grad;
+                }
             }
         }
 
@@ -2443,9 +5762,11 @@ where
             self.operand.backward(&self.operand_gradient.borrow());
         }
     }
+
     fn value(&self) -> Bor<Self::Value> {
         Bor::RefGuard(self.value.borrow())
     }
+
     fn needs_gradient(&self) -> bool {
         self.needs_gradient
     }
@@ -2457,32 +5778,207 @@ where
     }
 }
 
+/// A ragged batch of index lists ("bags"), with optional per-index weights.
+#[derive(Debug, Clone)]
+pub struct Bags {
+    indices: Vec<usize>,
+    offsets: Vec<usize>,
+    weights: Option<Vec<f32>>,
+}
+
+impl Default for Bags {
+    /// An empty batch, with no bags.
+    fn default() -> Self {
+        Bags {
+            indices: Vec::new(),
+            offsets: vec![0],
+            weights: None,
+        }
+    }
+}
+
+impl Bags {
+    fn new<T: AsRef<[usize]>>(bags: &[T]) -> Self {
+        let mut value = Bags::default();
+        value.set_indices(bags);
+        value
+    }
+
+    fn set_indices<T: AsRef<[usize]>>(&mut self, bags: &[T]) {
+        self.indices.clear();
+        self.offsets.clear();
+        self.offsets.push(0);
+
+        for bag in bags {
+            self.indices.extend_from_slice(bag.as_ref());
+            self.offsets.push(self.indices.len());
+        }
+
+        self.weights = None;
+    }
+
+    fn set_weights<T: AsRef<[f32]>>(&mut self, weights: &[T]) {
+        assert_eq!(
+            weights.len(),
+            self.len(),
+            "There must be one list of weights per bag."
+        );
+
+        let mut flat_weights = self.weights.take().unwrap_or_default();
+        flat_weights.clear();
+
+        for (bag, bag_weights) in weights.iter().enumerate() {
+            let bag_weights = bag_weights.as_ref();
+            assert_eq!(
+                bag_weights.len(),
+                self.offsets[bag + 1] - self.offsets[bag],
+                "There must be one weight per index."
+            );
+            flat_weights.extend_from_slice(bag_weights);
+        }
+
+        self.weights = Some(flat_weights);
+    }
+
+    fn assign(&mut self, other: &Bags) {
+        self.indices.clear();
+        self.indices.extend_from_slice(&other.indices);
+        self.offsets.clear();
+        self.offsets.extend_from_slice(&other.offsets);
+
+        match other.weights {
+            Some(ref other_weights) => {
+                let mut weights = self.weights.take().unwrap_or_default();
+                weights.clear();
+                weights.extend_from_slice(other_weights);
+                self.weights = Some(weights);
+            }
+            None => self.weights = None,
+        }
+    }
+
+    /// Number of bags.
+    pub fn len(&self) -> usize {
+        self.offsets.len() - 1
+    }
+
+    /// Whether there are no bags.
+    pub fn is_empty(&self) -> bool {
+        self.len() == 0
+    }
+
+    fn weight(&self, position: usize) -> f32 {
+        match self.weights {
+            Some(ref weights) => weights[position],
+            None => 1.0,
+        }
+    }
+}
+
+/// Input node holding a ragged batch of index lists.
 #[derive(Debug)]
-pub struct SumNode<OP> {
+pub struct BagInputNode {
+    pub(crate) value: RefCell<Bags>,
+}
+
+impl BagInputNode {
+    /// Create a new bag input node, with one index list per bag.
+    pub fn new<T: AsRef<[usize]>>(bags: &[T]) -> Variable<Self> {
+        Variable::new(
+            Rc::new(BagInputNode {
+                value: RefCell::new(Bags::new(bags)),
+            }),
+            Vec::new(),
+        )
+    }
+
+    /// Create a new bag input node where each index has a weight.
+    pub fn weighted<T: AsRef<[usize]>, W: AsRef<[f32]>>(
+        bags: &[T],
+        weights: &[W],
+    ) -> Variable<Self> {
+        let mut value = Bags::new(bags);
+        value.set_weights(weights);
+
+        Variable::new(
+            Rc::new(BagInputNode {
+                value: RefCell::new(value),
+            }),
+            Vec::new(),
+        )
+    }
+
+    pub(crate) fn set_indices<T: AsRef<[usize]>>(&self, bags: &[T]) {
+        self.value.borrow_mut().set_indices(bags);
+    }
+
+    pub(crate) fn set_weights<W: AsRef<[f32]>>(&self, weights: &[W]) {
+        self.value.borrow_mut().set_weights(weights);
+    }
+}
+
+impl Node for BagInputNode {
+    type Value = Bags;
+    type InputGradient = Arr;
+    fn forward(&self) {}
+    fn backward(&self, _: &Ref<Self::InputGradient>) {}
+    fn value(&self) -> Bor<Self::Value> {
+        Bor::RefGuard(self.value.borrow())
+    }
+    fn needs_gradient(&self) -> bool {
+        false
+    }
+    fn clear(&self) {}
+}
+
+/// How the embeddings in each bag are combined.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum Pooling {
+    /// Weighted sum of the embeddings.
+    Sum,
+    /// Weighted sum of the embeddings, divided by the size of the bag.
+    Mean,
+    /// Elementwise maximum of the weighted embeddings.
+    Max,
+}
+
+/// Pools the rows of an embedding parameter over each bag of indices.
+#[derive(Debug)]
+pub struct EmbeddingBagNode {
+    pooling: Pooling,
     value: RefCell<Arr>,
-    operand_gradient: RefCell<Arr>,
-    operand: Rc<OP>,
+    bags_value: RefCell<Bags>,
+    argmax: RefCell<Vec<usize>>,
+    index_gradient: RefCell<Arr>,
+    bags: Rc<BagInputNode>,
+    operand: Rc<ParameterNode>,
     needs_gradient: bool,
     counter: PassCounter,
 }
 
-impl<OP> SumNode<OP>
-where
-    OP: Node<Value = Arr>,
-{
-    pub fn new(operand: Rc<OP>) -> Self {
-        let value = {
-            let mut value = Arr::zeros((1, 1));
-            value.fill(operand.value().scalar_sum());
-            value
-        };
+impl EmbeddingBagNode {
+    pub fn new(operand: Rc<ParameterNode>, bags: Rc<BagInputNode>, pooling: Pooling) -> Self {
+        let bags_value = bags.value().clone();
+        let mut value = Arr::zeros((bags_value.len(), operand.value().cols()));
+        let mut argmax = vec![0; value.len()];
+
+        embedding_bag(
+            &mut value,
+            &mut argmax,
+            operand.value().deref(),
+            &bags_value,
+            pooling,
+        );
 
-        let gradient = operand.value().deref() * 0.0;
         let needs_gradient = operand.needs_gradient();
 
-        SumNode {
+        EmbeddingBagNode {
+            pooling: pooling,
             value: RefCell::new(value),
-            operand_gradient: RefCell::new(gradient),
+            bags_value: RefCell::new(bags_value),
+            argmax: RefCell::new(argmax),
+            index_gradient: RefCell::new(Arr::zeros((0, 0))),
+            bags: bags,
             operand: operand,
             needs_gradient: needs_gradient,
             counter: PassCounter::default(),
@@ -2490,10 +5986,50 @@ where
     }
 }
 
-impl<OP> Node for SumNode<OP>
-where
-    OP: Node<Value = Arr, InputGradient = Arr>,
-{
+/// Pool the rows of `embeddings` over every bag into the rows of `dest`.
+/// For max pooling, `argmax` records the position (in the flattened index
+/// list) of the maximal element for each element of `dest`.
+fn embedding_bag(
+    dest: &mut Arr,
+    argmax: &mut [usize],
+    embeddings: &Arr,
+    bags: &Bags,
+    pooling: Pooling,
+) {
+    dest.fill(0.0);
+
+    let dim = dest.cols();
+
+    for (bag, mut dest_row) in dest.genrows_mut().into_iter().enumerate() {
+        let (start, stop) = (bags.offsets[bag], bags.offsets[bag + 1]);
+        let dest_row = dest_row.fast_slice_mut();
+        let argmax = &mut argmax[bag * dim..(bag + 1) * dim];
+
+        for position in start..stop {
+            let weight = bags.weight(position);
+            let embedding = embeddings.subview(Axis(0), bags.indices[position]);
+            let embedding = embedding.fast_slice();
+
+            if pooling == Pooling::Max {
+                for (dest, idx, &x) in izip!(dest_row.iter_mut(), argmax.iter_mut(), embedding) {
+                    if position == start || weight * x > *dest {
+                        *dest = weight * x;
+                        *idx = position;
+                    }
+                }
+            } else {
+                numerics::simd_scaled_add(dest_row, embedding, weight);
+            }
+        }
+
+        if pooling == Pooling::Mean && stop > start {
+            let scale = 1.0 / (stop - start) as f32;
+            dest_row.iter_mut().for_each(|x| *x *= scale);
+        }
+    }
+}
+
+impl Node for EmbeddingBagNode {
     type Value = Arr;
     type InputGradient = Arr;
     fn forward(&self) {
@@ -2501,36 +6037,82 @@ where
             return;
         }
 
-        self.operand.forward();
+        let mut bags_value = self.bags_value.borrow_mut();
+        bags_value.assign(self.bags.value().deref());
 
-        let mut dest = self.value.borrow_mut();
-        dest[(0, 0)] = self.operand.value().scalar_sum();
+        let mut value = self.value.borrow_mut();
+
+        debug_assert_eq!(
+            value.rows(),
+            bags_value.len(),
+            "Number of bags must remain consistent between iterations."
+        );
+
+        embedding_bag(
+            &mut value,
+            &mut self.argmax.borrow_mut(),
+            self.operand.value().deref(),
+            &bags_value,
+            self.pooling,
+        );
     }
+
     fn backward(&self, gradient: &Ref<Self::InputGradient>) {
-        debug_assert!(gradient.len() == 1, "Input gradient must be a scalar.");
+        self.counter.backward();
 
-        match self.counter.backward() {
-            BackwardAction::Set => {
-                self.operand_gradient.borrow_mut().fill(gradient[(0, 0)]);
+        {
+            let bags = self.bags_value.borrow();
+            let argmax = self.argmax.borrow();
+            let mut index_gradient = self.index_gradient.borrow_mut();
+            let dim = gradient.cols();
+
+            if index_gradient.dim() != (bags.indices.len(), dim) {
+                *index_gradient = Arr::zeros((bags.indices.len(), dim));
             }
-            BackwardAction::Increment => {
-                self.operand_gradient
-                    .borrow_mut()
-                    .slice_add_assign(gradient[(0, 0)]);
+
+            // One gradient row per index, passed to the parameter sparsely.
+            for (bag, grad_row) in gradient.genrows().into_iter().enumerate() {
+                let (start, stop) = (bags.offsets[bag], bags.offsets[bag + 1]);
+                let grad_row = grad_row.fast_slice();
+                let argmax = &argmax[bag * dim..(bag + 1) * dim];
+
+                let scale = if self.pooling == Pooling::Mean {
+                    1.0 / (stop - start) as f32
+                } else {
+                    1.0
+                };
+
+                for position in start..stop {
+                    let weight = bags.weight(position);
+                    let mut index_row = index_gradient.subview_mut(Axis(0), position);
+                    let index_row = index_row.fast_slice_mut();
+
+                    if self.pooling == Pooling::Max {
+                        for (dest, &idx, &grad) in izip!(index_row.iter_mut(), argmax, grad_row) {
+                            *dest = if idx == position { weight * grad } else { 0.0 };
+                        }
+                    } else {
+                        numerics::simd_scaled_assign(index_row, grad_row, weight * scale);
+                    }
+                }
             }
-        }
 
-        if self.counter.recurse_backward() {
-            self.operand.backward(&self.operand_gradient.borrow());
+            self.operand
+                .gradient
+                .borrow_mut()
+                .add_sparse(&bags.indices, &index_gradient);
         }
+
+        self.counter.recurse_backward();
     }
+
     fn value(&self) -> Bor<Self::Value> {
         Bor::RefGuard(self.value.borrow())
     }
+
     fn needs_gradient(&self) -> bool {
         self.needs_gradient
     }
-
     fn clear(&self) {
         if !self.counter.is_zero() {
             self.operand.clear();
@@ -2539,27 +6121,124 @@ where
     }
 }
 
-/// An input node for integer indices into `ParameterNode`s, used
-/// for implementing indexable embedding layers.
+/// A sparse matrix in compressed sparse row (CSR) format.
+///
+/// The column indices and values of the non-zero entries of row `i`
+/// are held in `indices[indptr[i]..indptr[i + 1]]` and
+/// `values[indptr[i]..indptr[i + 1]]`.
+#[derive(Debug, Clone)]
+pub struct CsrMatrix {
+    shape: (usize, usize),
+    indptr: Vec<usize>,
+    indices: Vec<usize>,
+    values: Vec<f32>,
+}
+
+impl CsrMatrix {
+    /// Create a new CSR matrix of the given shape.
+    pub fn new(shape: (usize, usize), indptr: &[usize], indices: &[usize], values: &[f32]) -> Self {
+        let mut matrix = CsrMatrix {
+            shape: shape,
+            indptr: Vec::new(),
+            indices: Vec::new(),
+            values: Vec::new(),
+        };
+        matrix.assign(indptr, indices, values);
+
+        matrix
+    }
+
+    fn assign(&mut self, indptr: &[usize], indices: &[usize], values: &[f32]) {
+        assert_eq!(
+            indptr.len(),
+            self.shape.0 + 1,
+            "The row pointer must have one more entry than there are rows."
+        );
+        assert_eq!(
+            indices.len(),
+            values.len(),
+            "There must be one value per column index."
+        );
+        assert!(
+            indptr[0] == 0 && indptr[indptr.len() - 1] == indices.len(),
+            "The row pointer must start at zero and end at the number of non-zero entries."
+        );
+        assert!(
+            indptr.windows(2).all(|x| x[0] <= x[1]),
+            "The row pointer must be non-decreasing."
+        );
+        assert!(
+            indices.iter().all(|&x| x < self.shape.1),
+            "Column index out of bounds for {} columns.",
+            self.shape.1
+        );
+
+        self.indptr.clear();
+        self.indptr.extend_from_slice(indptr);
+        self.indices.clear();
+        self.indices.extend_from_slice(indices);
+        self.values.clear();
+        self.values.extend_from_slice(values);
+    }
+
+    /// Shape of the (dense) matrix.
+    pub fn dim(&self) -> (usize, usize) {
+        self.shape
+    }
+
+    /// Return the column indices and values of the non-zero entries of a row.
+    pub fn row(&self, row: usize) -> (&[usize], &[f32]) {
+        let (start, stop) = (self.indptr[row], self.indptr[row + 1]);
+
+        (&self.indices[start..stop], &self.values[start..stop])
+    }
+
+    /// Return the dense equivalent of this matrix.
+    pub fn to_dense(&self) -> Arr {
+        let mut dense = Arr::zeros(self.shape);
+
+        for (row, mut dense_row) in dense.genrows_mut().into_iter().enumerate() {
+            let (indices, values) = self.row(row);
+
+            for (&idx, &value) in indices.iter().zip(values.iter()) {
+                dense_row[idx] += value;
+            }
+        }
+
+        dense
+    }
+}
+
+/// Input node holding a sparse matrix.
 #[derive(Debug)]
-pub struct IndexInputNode {
-    pub(crate) value: RefCell<SmallVec<[usize; 4]>>,
+pub struct SparseInputNode {
+    pub(crate) value: RefCell<CsrMatrix>,
 }
 
-impl IndexInputNode {
-    /// Create a new index input node.
-    pub fn new(value: &[usize]) -> Variable<Self> {
+impl SparseInputNode {
+    /// Create a new sparse input node from CSR data. This fixes the
+    /// shape of the node in the graph.
+    pub fn new(
+        shape: (usize, usize),
+        indptr: &[usize],
+        indices: &[usize],
+        values: &[f32],
+    ) -> Variable<Self> {
         Variable::new(
-            Rc::new(IndexInputNode {
-                value: RefCell::new(SmallVec::from(value)),
+            Rc::new(SparseInputNode {
+                value: RefCell::new(CsrMatrix::new(shape, indptr, indices, values)),
             }),
             Vec::new(),
         )
     }
+
+    pub(crate) fn set_value(&self, indptr: &[usize], indices: &[usize], values: &[f32]) {
+        self.value.borrow_mut().assign(indptr, indices, values);
+    }
 }
 
-impl Node for IndexInputNode {
-    type Value = SmallVec<[usize; 4]>;
+impl Node for SparseInputNode {
+    type Value = CsrMatrix;
     type InputGradient = Arr;
     fn forward(&self) {}
     fn backward(&self, _: &Ref<Self::InputGradient>) {}
@@ -2572,40 +6251,61 @@ impl Node for IndexInputNode {
     fn clear(&self) {}
 }
 
+/// Product of a sparse input matrix and a dense parameter matrix.
 #[derive(Debug)]
-pub struct IndexNode<OP> {
+pub struct SparseDotNode {
     value: RefCell<Arr>,
-    index_value: RefCell<SmallVec<[usize; 4]>>,
-    operand_gradient: RefCell<Arr>,
-    index: Rc<IndexInputNode>,
-    operand: Rc<OP>,
+    lhs_value: RefCell<CsrMatrix>,
+    entry_gradient: RefCell<Arr>,
+    lhs: Rc<SparseInputNode>,
+    rhs: Rc<ParameterNode>,
     needs_gradient: bool,
     counter: PassCounter,
 }
 
-impl<OP> IndexNode<OP>
-where
-    OP: Node<Value = Arr>,
-{
-    pub fn new(operand: Rc<OP>, index: Rc<IndexInputNode>) -> Self {
-        let value = operand.value().select(Axis(0), &index.value()[..]);
-        let grad = &value * 0.0;
-        let idx_value = index.value().clone();
-        let needs_gradient = operand.needs_gradient();
+impl SparseDotNode {
+    pub fn new(lhs: Rc<SparseInputNode>, rhs: Rc<ParameterNode>) -> Self {
+        let lhs_value = lhs.value().clone();
 
-        IndexNode {
+        assert_eq!(
+            lhs_value.dim().1,
+            rhs.value().rows(),
+            "Cannot multiply a {:?} sparse matrix by a {:?} matrix.",
+            lhs_value.dim(),
+            rhs.value().dim()
+        );
+
+        let mut value = Arr::zeros((lhs_value.dim().0, rhs.value().cols()));
+        sparse_mat_mul(&mut value, &lhs_value, rhs.value().deref());
+
+        let needs_gradient = rhs.needs_gradient();
+
+        SparseDotNode {
             value: RefCell::new(value),
-            index_value: RefCell::new(idx_value),
-            operand_gradient: RefCell::new(grad),
-            index: index,
-            operand: operand,
+            lhs_value: RefCell::new(lhs_value),
+            entry_gradient: RefCell::new(Arr::zeros((0, 0))),
+            lhs: lhs,
+            rhs: rhs,
             needs_gradient: needs_gradient,
             counter: PassCounter::default(),
         }
     }
 }
 
-impl Node for IndexNode<ParameterNode> {
+fn sparse_mat_mul(dest: &mut Arr, lhs: &CsrMatrix, rhs: &Arr) {
+    dest.fill(0.0);
+
+    for (row, mut dest_row) in dest.genrows_mut().into_iter().enumerate() {
+        let dest_row = dest_row.fast_slice_mut();
+        let (indices, values) = lhs.row(row);
+
+        for (&idx, &value) in indices.iter().zip(values.iter()) {
+            numerics::simd_scaled_add(dest_row, rhs.subview(Axis(0), idx).fast_slice(), value);
+        }
+    }
+}
+
+impl Node for SparseDotNode {
     type Value = Arr;
     type InputGradient = Arr;
     fn forward(&self) {
@@ -2613,33 +6313,52 @@ impl Node for IndexNode<ParameterNode> {
             return;
         }
 
-        let operand_value = self.operand.value();
-
-        let mut idx_value = self.index_value.borrow_mut();
-        idx_value.clear();
-        idx_value.extend_from_slice(&self.index.value()[..]);
+        let mut lhs_value = self.lhs_value.borrow_mut();
 
-        let mut arr_value = self.value.borrow_mut();
+        {
+            let new_value = self.lhs.value();
+            lhs_value.assign(&new_value.indptr, &new_value.indices, &new_value.values);
+        }
 
-        debug_assert_eq!(
-            arr_value.shape()[0],
-            idx_value.len(),
-            "Result of indexing operation must maintain consistent shape between iterations."
+        sparse_mat_mul(
+            &mut self.value.borrow_mut(),
+            &lhs_value,
+            self.rhs.value().deref(),
         );
-
-        for (&idx, mut row) in idx_value.iter().zip(arr_value.genrows_mut()) {
-            let new_val = operand_value.subview(Axis(0), idx);
-
-            row.slice_assign(&new_val);
-        }
     }
 
     fn backward(&self, gradient: &Ref<Self::InputGradient>) {
         self.counter.backward();
-        self.operand
-            .gradient
-            .borrow_mut()
-            .accumulate_gradient((&self.index_value.borrow()[..], gradient.deref()));
+
+        {
+            let lhs_value = self.lhs_value.borrow();
+            let mut entry_gradient = self.entry_gradient.borrow_mut();
+            let shape = (lhs_value.indices.len(), gradient.cols());
+
+            if entry_gradient.dim() != shape {
+                *entry_gradient = Arr::zeros(shape);
+            }
+
+            // The gradient of the parameter is lhs^T * gradient: the row
+            // of every non-zero entry (i, j, v) receives v * gradient[i].
+            for (row, grad_row) in gradient.genrows().into_iter().enumerate() {
+                let grad_row = grad_row.fast_slice();
+
+                for entry in lhs_value.indptr[row]..lhs_value.indptr[row + 1] {
+                    numerics::simd_scaled_assign(
+                        entry_gradient.subview_mut(Axis(0), entry).fast_slice_mut(),
+                        grad_row,
+                        lhs_value.values[entry],
+                    );
+                }
+            }
+
+            self.rhs
+                .gradient
+                .borrow_mut()
+                .add_sparse(&lhs_value.indices, &entry_gradient);
+        }
+
         self.counter.recurse_backward();
     }
 
@@ -2652,7 +6371,7 @@ impl Node for IndexNode<ParameterNode> {
     }
     fn clear(&self) {
         if !self.counter.is_zero() {
-            self.operand.clear();
+            self.rhs.clear();
             self.counter.clear();
         }
     }
@@ -151,6 +151,22 @@ pub fn sigmoid(x: f32) -> f32 {
     }
 }
 
+/// Error function, using the Abramowitz and Stegun 7.1.26
+/// approximation (maximum absolute error of 1.5e-7).
+#[inline(always)]
+pub fn erf(x: f32) -> f32 {
+    let t = 1.0 / (1.0 + 0.3275911 * x.abs());
+    let polynomial =
+        t * (0.2548296 + t * (-0.28449672 + t * (1.4214138 + t * (-1.4531521 + t * 1.0614054))));
+    let y = 1.0 - polynomial * exp(-x * x);
+
+    if x < 0.0 {
+        -y
+    } else {
+        y
+    }
+}
+
 #[inline(always)]
 pub fn pow2(x: f32) -> f32 {
     x.powi(2)